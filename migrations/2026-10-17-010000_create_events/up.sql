@@ -0,0 +1,18 @@
+-- Your SQL goes here
+
+CREATE TABLE events (
+  id SERIAL PRIMARY KEY,
+  name varchar(255) NOT NULL,
+  venue varchar(255),
+  start_date TIMESTAMP NOT NULL,
+  end_date TIMESTAMP NOT NULL,
+  CHECK (start_date <= end_date)
+);
+
+CREATE TABLE circle_events (
+  id SERIAL PRIMARY KEY,
+  circle_id SERIAL REFERENCES circles(id) ON DELETE CASCADE,
+  event_id SERIAL REFERENCES events(id) ON DELETE CASCADE,
+  location varchar(255),
+  CONSTRAINT unique_circle_event_ids UNIQUE (circle_id, event_id)
+);
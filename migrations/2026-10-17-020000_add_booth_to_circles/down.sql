@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX circles_booth_idx;
+
+ALTER TABLE circles
+DROP COLUMN booth_hall,
+DROP COLUMN booth_block,
+DROP COLUMN booth_space,
+DROP COLUMN booth_half;
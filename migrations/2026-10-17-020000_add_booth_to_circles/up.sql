@@ -0,0 +1,30 @@
+-- Your SQL goes here
+
+ALTER TABLE circles
+ADD COLUMN booth_hall varchar(16),
+ADD COLUMN booth_block varchar(16),
+ADD COLUMN booth_space int,
+ADD COLUMN booth_half varchar(2);
+
+-- Split existing locations that already look like booth codes, by the same
+-- rules as `Booth::from_str`: an optional alphanumeric hall, an alphabetic
+-- block, a positive space of at most nine digits and an optional half, with
+-- hall and block no longer than their columns. Anything else is left unset.
+UPDATE circles
+SET booth_hall = upper(parsed.m[1]),
+    booth_block = upper(parsed.m[2]),
+    booth_space = parsed.m[3]::int,
+    booth_half = nullif(lower(parsed.m[4]), '')
+FROM (
+  SELECT id, regexp_match(
+    location,
+    '^\s*(?:([[:alnum:]]{1,16})\s+)?([[:alpha:]]{1,16})-?([0-9]{1,9})(ab|a|b)?\s*$',
+    'i'
+  ) AS m
+  FROM circles
+) AS parsed
+WHERE circles.id = parsed.id
+AND parsed.m IS NOT NULL
+AND parsed.m[3]::int > 0;
+
+CREATE INDEX circles_booth_idx ON circles (booth_hall, booth_block, booth_space, booth_half);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE wishlist_bundles;
+
+DROP TABLE wishlist_goods;
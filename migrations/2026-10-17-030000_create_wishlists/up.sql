@@ -0,0 +1,17 @@
+-- Your SQL goes here
+
+CREATE TABLE wishlist_goods (
+  id SERIAL PRIMARY KEY,
+  user_id SERIAL REFERENCES users(id) ON DELETE CASCADE,
+  goods_id SERIAL REFERENCES goods(id) ON DELETE CASCADE,
+  quantity int NOT NULL DEFAULT 1 CHECK (quantity > 0),
+  CONSTRAINT unique_wishlist_goods_ids UNIQUE (user_id, goods_id)
+);
+
+CREATE TABLE wishlist_bundles (
+  id SERIAL PRIMARY KEY,
+  user_id SERIAL REFERENCES users(id) ON DELETE CASCADE,
+  bundle_id SERIAL REFERENCES bundles(id) ON DELETE CASCADE,
+  quantity int NOT NULL DEFAULT 1 CHECK (quantity > 0),
+  CONSTRAINT unique_wishlist_bundle_ids UNIQUE (user_id, bundle_id)
+);
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+
+CREATE TABLE collections (
+  id SERIAL PRIMARY KEY,
+  user_id SERIAL REFERENCES users(id) ON DELETE CASCADE,
+  name varchar(255) NOT NULL,
+  description text,
+  share_token CHAR(24) UNIQUE
+);
+
+CREATE TABLE collection_goods (
+  id SERIAL PRIMARY KEY,
+  collection_id SERIAL REFERENCES collections(id) ON DELETE CASCADE,
+  goods_id SERIAL REFERENCES goods(id) ON DELETE CASCADE,
+  position int NOT NULL DEFAULT 0,
+  note text,
+  CONSTRAINT unique_collection_goods_ids UNIQUE (collection_id, goods_id)
+);
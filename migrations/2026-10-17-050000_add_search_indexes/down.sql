@@ -0,0 +1,12 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX goods_fts_idx;
+DROP INDEX circles_fts_idx;
+
+DROP INDEX refs_name_trgm_idx;
+DROP INDEX characters_name_trgm_idx;
+DROP INDEX goods_name_trgm_idx;
+DROP INDEX artists_name_trgm_idx;
+DROP INDEX circles_name_trgm_idx;
+
+DROP EXTENSION IF EXISTS pg_trgm;
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX circles_name_trgm_idx ON circles USING gin ((coalesce(name, '')) gin_trgm_ops);
+CREATE INDEX artists_name_trgm_idx ON artists USING gin (name gin_trgm_ops);
+CREATE INDEX goods_name_trgm_idx ON goods USING gin ((coalesce(name, '')) gin_trgm_ops);
+CREATE INDEX characters_name_trgm_idx ON characters USING gin (name gin_trgm_ops);
+CREATE INDEX refs_name_trgm_idx ON refs USING gin (name gin_trgm_ops);
+
+CREATE INDEX circles_fts_idx ON circles
+USING gin (to_tsvector('simple', coalesce(name, '') || ' ' || coalesce(description, '')));
+
+CREATE INDEX goods_fts_idx ON goods
+USING gin (to_tsvector('simple', coalesce(name, '') || ' ' || coalesce(description, '')));
@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX characters_name_chosung_idx;
+DROP INDEX characters_name_jamo_idx;
+DROP INDEX goods_name_chosung_idx;
+DROP INDEX goods_name_jamo_idx;
+DROP INDEX artists_name_chosung_idx;
+DROP INDEX artists_name_jamo_idx;
+DROP INDEX circles_name_chosung_idx;
+DROP INDEX circles_name_jamo_idx;
+
+DROP FUNCTION hangul_chosung(TEXT);
+DROP FUNCTION hangul_jamo(TEXT);
@@ -0,0 +1,109 @@
+-- Your SQL goes here
+
+-- Decomposes Hangul into compatibility jamo so that a partially typed
+-- syllable such as '앜' matches the start of '아카'. Compound vowels and
+-- finals are split into the keystrokes that produce them.
+CREATE FUNCTION hangul_jamo(input TEXT) RETURNS TEXT AS $$
+DECLARE
+    initials TEXT[] := ARRAY[
+        'ㄱ', 'ㄲ', 'ㄴ', 'ㄷ', 'ㄸ', 'ㄹ', 'ㅁ', 'ㅂ', 'ㅃ', 'ㅅ',
+        'ㅆ', 'ㅇ', 'ㅈ', 'ㅉ', 'ㅊ', 'ㅋ', 'ㅌ', 'ㅍ', 'ㅎ'
+    ];
+    medials TEXT[] := ARRAY[
+        'ㅏ', 'ㅐ', 'ㅑ', 'ㅒ', 'ㅓ', 'ㅔ', 'ㅕ', 'ㅖ', 'ㅗ', 'ㅗㅏ',
+        'ㅗㅐ', 'ㅗㅣ', 'ㅛ', 'ㅜ', 'ㅜㅓ', 'ㅜㅔ', 'ㅜㅣ', 'ㅠ', 'ㅡ', 'ㅡㅣ',
+        'ㅣ'
+    ];
+    finals TEXT[] := ARRAY[
+        'ㄱ', 'ㄲ', 'ㄱㅅ', 'ㄴ', 'ㄴㅈ', 'ㄴㅎ', 'ㄷ', 'ㄹ', 'ㄹㄱ', 'ㄹㅁ',
+        'ㄹㅂ', 'ㄹㅅ', 'ㄹㅌ', 'ㄹㅍ', 'ㄹㅎ', 'ㅁ', 'ㅂ', 'ㅂㅅ', 'ㅅ', 'ㅆ',
+        'ㅇ', 'ㅈ', 'ㅊ', 'ㅋ', 'ㅌ', 'ㅍ', 'ㅎ'
+    ];
+    compounds TEXT := 'ㄳㄵㄶㄺㄻㄼㄽㄾㄿㅀㅄㅘㅙㅚㅝㅞㅟㅢ';
+    decomposed TEXT[] := ARRAY[
+        'ㄱㅅ', 'ㄴㅈ', 'ㄴㅎ', 'ㄹㄱ', 'ㄹㅁ', 'ㄹㅂ', 'ㄹㅅ', 'ㄹㅌ', 'ㄹㅍ', 'ㄹㅎ',
+        'ㅂㅅ', 'ㅗㅏ', 'ㅗㅐ', 'ㅗㅣ', 'ㅜㅓ', 'ㅜㅔ', 'ㅜㅣ', 'ㅡㅣ'
+    ];
+    result TEXT := '';
+    ch TEXT;
+    code INTEGER;
+    idx INTEGER;
+BEGIN
+    IF input IS NULL THEN
+        RETURN NULL;
+    END IF;
+
+    FOREACH ch IN ARRAY regexp_split_to_array(input, '') LOOP
+        code := ascii(ch);
+
+        IF code BETWEEN 44032 AND 55203 THEN
+            -- Precomposed syllables (U+AC00..U+D7A3)
+            idx := code - 44032;
+            result := result || initials[idx / 588 + 1] || medials[idx % 588 / 28 + 1];
+
+            IF idx % 28 > 0 THEN
+                result := result || finals[idx % 28];
+            END IF;
+        ELSIF code BETWEEN 4352 AND 4370 THEN
+            -- Conjoining initials (U+1100..U+1112), as produced by NFD
+            result := result || initials[code - 4352 + 1];
+        ELSIF code BETWEEN 4449 AND 4469 THEN
+            -- Conjoining medials (U+1161..U+1175)
+            result := result || medials[code - 4449 + 1];
+        ELSIF code BETWEEN 4520 AND 4546 THEN
+            -- Conjoining finals (U+11A8..U+11C2)
+            result := result || finals[code - 4520 + 1];
+        ELSIF strpos(compounds, ch) > 0 THEN
+            result := result || decomposed[strpos(compounds, ch)];
+        ELSE
+            result := result || ch;
+        END IF;
+    END LOOP;
+
+    RETURN result;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE STRICT PARALLEL SAFE;
+
+-- Replaces every Hangul syllable with its initial consonant (chosung), so
+-- that 'ㅂㄹㅇㅋ' matches '블루아카'.
+CREATE FUNCTION hangul_chosung(input TEXT) RETURNS TEXT AS $$
+DECLARE
+    initials TEXT[] := ARRAY[
+        'ㄱ', 'ㄲ', 'ㄴ', 'ㄷ', 'ㄸ', 'ㄹ', 'ㅁ', 'ㅂ', 'ㅃ', 'ㅅ',
+        'ㅆ', 'ㅇ', 'ㅈ', 'ㅉ', 'ㅊ', 'ㅋ', 'ㅌ', 'ㅍ', 'ㅎ'
+    ];
+    result TEXT := '';
+    ch TEXT;
+    code INTEGER;
+BEGIN
+    IF input IS NULL THEN
+        RETURN NULL;
+    END IF;
+
+    FOREACH ch IN ARRAY regexp_split_to_array(input, '') LOOP
+        code := ascii(ch);
+
+        IF code BETWEEN 44032 AND 55203 THEN
+            result := result || initials[(code - 44032) / 588 + 1];
+        ELSIF code BETWEEN 4352 AND 4370 THEN
+            result := result || initials[code - 4352 + 1];
+        ELSIF code BETWEEN 4449 AND 4469 OR code BETWEEN 4520 AND 4546 THEN
+            -- Conjoining medials and finals belong to the preceding initial
+            NULL;
+        ELSE
+            result := result || ch;
+        END IF;
+    END LOOP;
+
+    RETURN result;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE STRICT PARALLEL SAFE;
+
+CREATE INDEX circles_name_jamo_idx ON circles USING gin (hangul_jamo(name) gin_trgm_ops);
+CREATE INDEX circles_name_chosung_idx ON circles USING gin (hangul_chosung(name) gin_trgm_ops);
+CREATE INDEX artists_name_jamo_idx ON artists USING gin (hangul_jamo(name) gin_trgm_ops);
+CREATE INDEX artists_name_chosung_idx ON artists USING gin (hangul_chosung(name) gin_trgm_ops);
+CREATE INDEX goods_name_jamo_idx ON goods USING gin (hangul_jamo(name) gin_trgm_ops);
+CREATE INDEX goods_name_chosung_idx ON goods USING gin (hangul_chosung(name) gin_trgm_ops);
+CREATE INDEX characters_name_jamo_idx ON characters USING gin (hangul_jamo(name) gin_trgm_ops);
+CREATE INDEX characters_name_chosung_idx ON characters USING gin (hangul_chosung(name) gin_trgm_ops);
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX refs_name_key_idx;
+DROP INDEX characters_name_key_idx;
+DROP INDEX goods_name_key_idx;
+DROP INDEX artists_name_key_idx;
+DROP INDEX circles_name_key_idx;
+
+DROP FUNCTION search_key(TEXT);
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+
+-- Folds the ways Japanese names get written into one search key: NFKC takes
+-- care of half-width kana and full-width Latin, hiragana is mapped onto
+-- katakana and long-vowel marks are dropped, so 'ｱﾘｽ', 'アリス', 'ありす' and
+-- 'アリース' all become 'アリス'.
+CREATE FUNCTION search_key(input TEXT) RETURNS TEXT AS $$
+    SELECT replace(
+        translate(
+            lower(normalize(input, NFKC)),
+            'ぁあぃいぅうぇえぉおかがきぎくぐけげこごさざしじすずせぜそぞただちぢっつづてでとどなにぬねのはばぱひびぴふぶぷへべぺほぼぽまみむめもゃやゅゆょよらりるれろゎわゐゑをんゔゕゖゝゞ',
+            'ァアィイゥウェエォオカガキギクグケゲコゴサザシジスズセゼソゾタダチヂッツヅテデトドナニヌネノハバパヒビピフブプヘベペホボポマミムメモャヤュユョヨラリルレロヮワヰヱヲンヴヵヶヽヾ'
+        ),
+        'ー',
+        ''
+    )
+$$ LANGUAGE sql IMMUTABLE STRICT PARALLEL SAFE;
+
+CREATE INDEX circles_name_key_idx ON circles USING gin (search_key(name) gin_trgm_ops);
+CREATE INDEX artists_name_key_idx ON artists USING gin (search_key(name) gin_trgm_ops);
+CREATE INDEX goods_name_key_idx ON goods USING gin (search_key(name) gin_trgm_ops);
+CREATE INDEX characters_name_key_idx ON characters USING gin (search_key(name) gin_trgm_ops);
+CREATE INDEX refs_name_key_idx ON refs USING gin (search_key(name) gin_trgm_ops);
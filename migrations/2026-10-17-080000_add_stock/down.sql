@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE bundles
+DROP COLUMN availability,
+DROP COLUMN stock;
+
+ALTER TABLE goods
+DROP COLUMN availability,
+DROP COLUMN stock;
+
+DROP TYPE availability_type;
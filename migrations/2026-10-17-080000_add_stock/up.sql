@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+CREATE TYPE availability_type AS ENUM ('available', 'low', 'sold_out', 'not_on_sale');
+
+ALTER TABLE goods
+ADD COLUMN stock INTEGER CHECK (stock >= 0),
+ADD COLUMN availability availability_type NOT NULL DEFAULT 'available';
+
+ALTER TABLE bundles
+ADD COLUMN stock INTEGER CHECK (stock >= 0),
+ADD COLUMN availability availability_type NOT NULL DEFAULT 'available';
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE order_items;
+DROP TABLE orders;
+DROP TABLE order_form_items;
+DROP TABLE order_forms;
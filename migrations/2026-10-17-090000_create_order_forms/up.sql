@@ -0,0 +1,40 @@
+-- Your SQL goes here
+
+CREATE TABLE order_forms (
+  id SERIAL PRIMARY KEY,
+  circle_id SERIAL REFERENCES circles(id) ON DELETE CASCADE,
+  title varchar(255) NOT NULL,
+  description text,
+  opens_at TIMESTAMP NOT NULL,
+  closes_at TIMESTAMP NOT NULL,
+  CHECK (opens_at < closes_at)
+);
+
+CREATE TABLE order_form_items (
+  id SERIAL PRIMARY KEY,
+  order_form_id SERIAL REFERENCES order_forms(id) ON DELETE CASCADE,
+  goods_id int REFERENCES goods(id) ON DELETE CASCADE,
+  bundle_id int REFERENCES bundles(id) ON DELETE CASCADE,
+  max_quantity int CHECK (max_quantity > 0),
+  CHECK ((goods_id IS NULL) <> (bundle_id IS NULL)),
+  CONSTRAINT unique_order_form_goods_ids UNIQUE (order_form_id, goods_id),
+  CONSTRAINT unique_order_form_bundle_ids UNIQUE (order_form_id, bundle_id)
+);
+
+CREATE TABLE orders (
+  id SERIAL PRIMARY KEY,
+  order_form_id SERIAL REFERENCES order_forms(id) ON DELETE CASCADE,
+  user_id SERIAL REFERENCES users(id) ON DELETE CASCADE,
+  note text,
+  created_at TIMESTAMP NOT NULL DEFAULT now(),
+  CONSTRAINT unique_order_form_user_ids UNIQUE (order_form_id, user_id)
+);
+
+CREATE TABLE order_items (
+  id SERIAL PRIMARY KEY,
+  order_id SERIAL REFERENCES orders(id) ON DELETE CASCADE,
+  order_form_item_id SERIAL REFERENCES order_form_items(id) ON DELETE CASCADE,
+  quantity int NOT NULL CHECK (quantity > 0),
+  price int NOT NULL DEFAULT 0,
+  CONSTRAINT unique_order_item_ids UNIQUE (order_id, order_form_item_id)
+);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE survey_responses;
+DROP TABLE survey_items;
+DROP TABLE surveys;
@@ -0,0 +1,27 @@
+-- Your SQL goes here
+
+CREATE TABLE surveys (
+  id SERIAL PRIMARY KEY,
+  circle_id SERIAL REFERENCES circles(id) ON DELETE CASCADE,
+  title varchar(255) NOT NULL,
+  description text,
+  closes_at TIMESTAMP NOT NULL
+);
+
+CREATE TABLE survey_items (
+  id SERIAL PRIMARY KEY,
+  survey_id SERIAL REFERENCES surveys(id) ON DELETE CASCADE,
+  goods_id int REFERENCES goods(id) ON DELETE CASCADE,
+  bundle_id int REFERENCES bundles(id) ON DELETE CASCADE,
+  CHECK ((goods_id IS NULL) <> (bundle_id IS NULL)),
+  CONSTRAINT unique_survey_goods_ids UNIQUE (survey_id, goods_id),
+  CONSTRAINT unique_survey_bundle_ids UNIQUE (survey_id, bundle_id)
+);
+
+CREATE TABLE survey_responses (
+  id SERIAL PRIMARY KEY,
+  survey_item_id SERIAL REFERENCES survey_items(id) ON DELETE CASCADE,
+  user_id SERIAL REFERENCES users(id) ON DELETE CASCADE,
+  quantity int NOT NULL CHECK (quantity > 0),
+  CONSTRAINT unique_survey_response_ids UNIQUE (survey_item_id, user_id)
+);
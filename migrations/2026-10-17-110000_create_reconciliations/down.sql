@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE reconciliation_deposits;
+DROP TABLE reconciliation_payments;
+DROP TABLE reconciliations;
+DROP TYPE match_status_type;
@@ -0,0 +1,30 @@
+-- Your SQL goes here
+
+CREATE TYPE match_status_type AS ENUM ('matched', 'fuzzy', 'ambiguous', 'unmatched', 'resolved');
+
+CREATE TABLE reconciliations (
+  id SERIAL PRIMARY KEY,
+  circle_id SERIAL REFERENCES circles(id) ON DELETE CASCADE,
+  title varchar(255) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE TABLE reconciliation_payments (
+  id SERIAL PRIMARY KEY,
+  reconciliation_id SERIAL REFERENCES reconciliations(id) ON DELETE CASCADE,
+  depositor_name varchar(255) NOT NULL,
+  amount int NOT NULL CHECK (amount > 0),
+  note varchar(255)
+);
+
+CREATE TABLE reconciliation_deposits (
+  id SERIAL PRIMARY KEY,
+  reconciliation_id SERIAL REFERENCES reconciliations(id) ON DELETE CASCADE,
+  line int NOT NULL,
+  depositor_name varchar(255) NOT NULL,
+  amount int NOT NULL CHECK (amount > 0),
+  deposited_at varchar(255),
+  status match_status_type NOT NULL,
+  payment_id int UNIQUE REFERENCES reconciliation_payments(id) ON DELETE SET NULL,
+  candidate_ids int[] NOT NULL DEFAULT '{}'
+);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE prepayment_items;
+DROP TABLE prepayments;
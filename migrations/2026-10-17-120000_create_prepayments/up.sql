@@ -0,0 +1,26 @@
+-- Your SQL goes here
+
+CREATE TABLE prepayments (
+  id SERIAL PRIMARY KEY,
+  circle_id SERIAL REFERENCES circles(id) ON DELETE CASCADE,
+  buyer_name varchar(255) NOT NULL,
+  contact varchar(255),
+  note text,
+  user_id int REFERENCES users(id) ON DELETE SET NULL,
+  order_id int UNIQUE REFERENCES orders(id) ON DELETE SET NULL,
+  code char(8) NOT NULL UNIQUE,
+  created_at TIMESTAMP NOT NULL DEFAULT now(),
+  redeemed_at TIMESTAMP,
+  redeemed_by int REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE TABLE prepayment_items (
+  id SERIAL PRIMARY KEY,
+  prepayment_id SERIAL REFERENCES prepayments(id) ON DELETE CASCADE,
+  goods_id int REFERENCES goods(id) ON DELETE CASCADE,
+  bundle_id int REFERENCES bundles(id) ON DELETE CASCADE,
+  quantity int NOT NULL CHECK (quantity > 0),
+  CHECK ((goods_id IS NULL) <> (bundle_id IS NULL)),
+  CONSTRAINT unique_prepayment_goods_ids UNIQUE (prepayment_id, goods_id),
+  CONSTRAINT unique_prepayment_bundle_ids UNIQUE (prepayment_id, bundle_id)
+);
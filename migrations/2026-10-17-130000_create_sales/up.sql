@@ -0,0 +1,21 @@
+-- Your SQL goes here
+
+CREATE TABLE sales (
+  id SERIAL PRIMARY KEY,
+  circle_id SERIAL REFERENCES circles(id) ON DELETE CASCADE,
+  user_id int REFERENCES users(id) ON DELETE SET NULL,
+  total int NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX sales_circle_id_idx ON sales (circle_id, created_at);
+
+-- Sales outlive the goods and bundles they list, so both ids may end up null
+CREATE TABLE sale_items (
+  id SERIAL PRIMARY KEY,
+  sale_id SERIAL REFERENCES sales(id) ON DELETE CASCADE,
+  goods_id int REFERENCES goods(id) ON DELETE SET NULL,
+  bundle_id int REFERENCES bundles(id) ON DELETE SET NULL,
+  quantity int NOT NULL CHECK (quantity > 0),
+  price int NOT NULL
+);
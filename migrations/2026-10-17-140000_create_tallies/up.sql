@@ -0,0 +1,22 @@
+-- Your SQL goes here
+
+CREATE TABLE tallies (
+  id SERIAL PRIMARY KEY,
+  circle_id SERIAL REFERENCES circles(id) ON DELETE CASCADE,
+  event_id int REFERENCES events(id) ON DELETE SET NULL,
+  title varchar(255) NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE TABLE tally_items (
+  id SERIAL PRIMARY KEY,
+  tally_id SERIAL REFERENCES tallies(id) ON DELETE CASCADE,
+  goods_id int REFERENCES goods(id) ON DELETE CASCADE,
+  bundle_id int REFERENCES bundles(id) ON DELETE CASCADE,
+  starting int NOT NULL CHECK (starting >= 0),
+  remaining int NOT NULL CHECK (remaining >= 0 AND remaining <= starting),
+  price int,
+  CHECK ((goods_id IS NULL) <> (bundle_id IS NULL)),
+  CONSTRAINT unique_tally_goods_ids UNIQUE (tally_id, goods_id),
+  CONSTRAINT unique_tally_bundle_ids UNIQUE (tally_id, bundle_id)
+);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE goods_in_bundle
+DROP COLUMN weight;
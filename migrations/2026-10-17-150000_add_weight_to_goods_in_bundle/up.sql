@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- Relative chance of drawing each goods from a random bundle.
+ALTER TABLE goods_in_bundle
+ADD COLUMN weight INT NOT NULL DEFAULT 1 CHECK (weight > 0);
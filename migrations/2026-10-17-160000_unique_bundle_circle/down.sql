@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE circle_bundles
+DROP CONSTRAINT unique_circle_bundle_bundle_id;
@@ -0,0 +1,27 @@
+-- Your SQL goes here
+
+-- A bundle belongs to exactly one circle. Bundles already linked to more than
+-- one circle have to be sorted out by hand before this can run, so refuse
+-- rather than guess which circle keeps them.
+DO $$
+DECLARE
+  shared TEXT;
+BEGIN
+  SELECT string_agg(bundle_id::TEXT, ', ' ORDER BY bundle_id)
+  INTO shared
+  FROM (
+    SELECT bundle_id
+    FROM circle_bundles
+    GROUP BY bundle_id
+    HAVING count(*) > 1
+  ) duplicates;
+
+  IF shared IS NOT NULL THEN
+    RAISE EXCEPTION 'Bundles linked to more than one circle: %', shared
+      USING HINT = 'Remove the extra circle_bundles rows for these bundles and run the migration again.';
+  END IF;
+END
+$$;
+
+ALTER TABLE circle_bundles
+ADD CONSTRAINT unique_circle_bundle_bundle_id UNIQUE (bundle_id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE character_aliases;
+DROP TYPE alias_kind_type;
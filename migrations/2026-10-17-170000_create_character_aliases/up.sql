@@ -0,0 +1,15 @@
+-- Your SQL goes here
+
+CREATE TYPE alias_kind_type AS ENUM ('name', 'nickname');
+
+-- Other names a character goes by. `language` is a tag such as `ko`, `ja` or
+-- `en`; `name` is the character's name in that language and `nickname` one
+-- that fans use.
+CREATE TABLE character_aliases (
+  id SERIAL PRIMARY KEY,
+  character_id INT NOT NULL REFERENCES characters(id) ON DELETE CASCADE,
+  language varchar(16) NOT NULL,
+  kind alias_kind_type NOT NULL,
+  name varchar(255) NOT NULL,
+  UNIQUE (character_id, language, kind, name)
+);
@@ -3,7 +3,7 @@ use serde::Serialize;
 
 pub type CustomError = Custom<Json<ErrorInfo>>;
 
-#[derive(Serialize)]
+#[derive(Serialize, Debug)]
 pub struct ErrorInfo {
     success: bool,
     message: String,
@@ -18,30 +18,75 @@ use routes::artists::{
 };
 use routes::auth::{add_user, check_handle, delete_me, get_me, login, logout, patch_me, new_twitter_oauth, check_twitter_oauth};
 use routes::bundles::{
-    delete_bundle, delete_bundle_goods, get_bundle_by_id, get_bundles, patch_bundle,
-    patch_bundle_goods, post_bundle_goods, post_circle_bundle,
+    delete_bundle, delete_bundle_goods, get_bundle_by_id, get_bundle_completion, get_bundle_odds,
+    get_bundles, get_full_bundle, patch_bundle, patch_bundle_goods, patch_bundle_stock,
+    post_bundle_goods, post_bundle_selection, post_circle_bundle,
 };
 use routes::categories::{
     delete_category, get_categories, get_category_by_id, patch_category, post_category,
 };
 use routes::characters::{
-    delete_character, get_character_by_id, get_characters, patch_character, post_character,
+    delete_character, delete_character_alias, get_character_by_id, get_characters,
+    patch_character, post_character, post_character_alias,
 };
 use routes::circles::{get_circles_with_prepayment, delete_circle, get_circle_by_id, get_circles, patch_circle, post_circle};
+use routes::collections::{
+    delete_collection, delete_collection_goods, delete_collection_share, get_collection_by_id,
+    get_my_collections, get_shared_collection, patch_collection, patch_collection_goods,
+    post_collection, post_collection_goods, post_collection_share,
+};
+use routes::events::{
+    delete_circle_event, delete_event, get_event_by_id, get_event_circles, get_events,
+    patch_circle_event, patch_event, post_circle_event, post_event,
+};
 use routes::goods::{
     delete_good_character, delete_goods, get_goods, get_goods_by_id, patch_goods,
-    post_circle_goods, post_good_character,
+    patch_goods_stock, post_circle_goods, post_good_character,
 };
 use routes::images::{upload_image, get_image};
 use routes::links::{delete_link, get_link_by_id, get_links, patch_link, post_circle_link};
+use routes::orders::{
+    delete_order, delete_order_form, delete_order_form_item, get_circle_order_forms,
+    get_my_orders, get_order_form_by_id, get_order_form_orders, patch_order_form,
+    patch_order_form_item, post_order, post_order_form, post_order_form_item,
+};
+use routes::planner::post_circle_route;
+use routes::prepayments::{
+    delete_prepayment, get_circle_prepayments, get_my_prepayments, get_pickup_code,
+    get_prepayment_by_id, patch_prepayment, post_order_form_prepayments, post_prepayment,
+    redeem_pickup_code,
+};
+use routes::reconciliations::{
+    delete_reconciliation, get_circle_reconciliations, get_reconciliation_by_id,
+    patch_reconciliation_deposit, post_reconciliation,
+};
 use routes::references::{
     delete_reference, get_reference_by_id, get_references, patch_reference, post_reference,
 };
 
+use routes::sales::{delete_last_sale, get_circle_sales, get_sales_summary, post_sale};
+use routes::search::search;
+use routes::stream::{stream, ChangeFeed};
+use routes::surveys::{
+    delete_survey, delete_survey_item, get_circle_surveys, get_my_survey_response,
+    get_survey_by_id, get_survey_results, get_survey_results_csv, patch_survey, post_survey,
+    post_survey_item, put_my_survey_response,
+};
+use routes::tallies::{
+    delete_tally, get_circle_tallies, get_tally_by_id, get_tally_report, get_tally_report_csv,
+    post_tally,
+};
+use routes::wishlists::{
+    delete_wishlist_bundle, delete_wishlist_goods, get_wishlist, patch_wishlist_bundle,
+    patch_wishlist_goods, post_wishlist_bundle, post_wishlist_goods,
+};
+
 mod error_handler;
 mod models;
 mod routes;
 mod schema;
+#[cfg(test)]
+mod test_support;
 mod utils;
 
 type DbPool = Pool<ConnectionManager<PgConnection>>;
@@ -66,7 +111,7 @@ impl Fairing for CORS {
         response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
         response.set_header(Header::new(
             "Access-Control-Allow-Methods",
-            "POST, GET, PATCH, DELETE, OPTIONS",
+            "POST, GET, PUT, PATCH, DELETE, OPTIONS",
         ));
         response.set_header(Header::new("Access-Control-Allow-Headers", "*"));
         response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
@@ -85,6 +130,7 @@ fn rocket() -> _ {
 
     let rocket = rocket::build()
         .manage(pool)
+        .manage(ChangeFeed::default())
         .mount(
             "/",
             routes![
@@ -112,25 +158,43 @@ fn rocket() -> _ {
                 post_circle,
                 patch_circle,
                 delete_circle,
+                post_circle_route,
+                post_event,
+                get_events,
+                get_event_by_id,
+                patch_event,
+                delete_event,
+                get_event_circles,
+                post_circle_event,
+                patch_circle_event,
+                delete_circle_event,
                 post_circle_goods,
                 get_goods,
                 get_goods_by_id,
                 patch_goods,
+                patch_goods_stock,
                 delete_goods,
                 post_good_character,
                 delete_good_character,
                 post_circle_bundle,
                 get_bundles,
                 get_bundle_by_id,
+                get_full_bundle,
+                get_bundle_odds,
+                get_bundle_completion,
                 patch_bundle,
+                patch_bundle_stock,
                 delete_bundle,
                 post_bundle_goods,
+                post_bundle_selection,
                 delete_bundle_goods,
                 post_character,
                 get_characters,
                 get_character_by_id,
                 patch_character,
                 delete_character,
+                post_character_alias,
+                delete_character_alias,
                 post_reference,
                 get_references,
                 get_reference_by_id,
@@ -147,6 +211,73 @@ fn rocket() -> _ {
                 patch_link,
                 delete_link,
                 patch_bundle_goods,
+                get_wishlist,
+                post_wishlist_goods,
+                patch_wishlist_goods,
+                delete_wishlist_goods,
+                post_wishlist_bundle,
+                patch_wishlist_bundle,
+                delete_wishlist_bundle,
+                post_collection,
+                get_my_collections,
+                get_collection_by_id,
+                patch_collection,
+                delete_collection,
+                post_collection_goods,
+                patch_collection_goods,
+                delete_collection_goods,
+                post_collection_share,
+                delete_collection_share,
+                get_shared_collection,
+                search,
+                stream,
+                post_order_form,
+                get_circle_order_forms,
+                get_order_form_by_id,
+                patch_order_form,
+                delete_order_form,
+                post_order_form_item,
+                patch_order_form_item,
+                delete_order_form_item,
+                post_order,
+                get_order_form_orders,
+                get_my_orders,
+                delete_order,
+                post_survey,
+                get_circle_surveys,
+                get_survey_by_id,
+                patch_survey,
+                delete_survey,
+                post_survey_item,
+                delete_survey_item,
+                get_my_survey_response,
+                put_my_survey_response,
+                get_survey_results,
+                get_survey_results_csv,
+                post_reconciliation,
+                get_circle_reconciliations,
+                get_reconciliation_by_id,
+                delete_reconciliation,
+                patch_reconciliation_deposit,
+                post_prepayment,
+                post_order_form_prepayments,
+                get_circle_prepayments,
+                get_prepayment_by_id,
+                patch_prepayment,
+                delete_prepayment,
+                get_my_prepayments,
+                get_pickup_code,
+                redeem_pickup_code,
+                post_sale,
+                get_circle_sales,
+                delete_last_sale,
+                get_sales_summary,
+                post_tally,
+                get_circle_tallies,
+                get_tally_by_id,
+                delete_tally,
+                get_tally_report,
+                get_tally_report_csv,
                 all_options,
             ],
         )
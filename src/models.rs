@@ -10,6 +10,24 @@ use serde::Deserialize;
 
 use crate::error_handler::{CustomError, ErrorInfo};
 
+#[allow(non_camel_case_types)]
+#[derive(diesel_derive_enum::DbEnum, Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[ExistingTypePath = "crate::schema::sql_types::AliasKindType"]
+pub enum AliasKindEnum {
+    name,
+    nickname,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(diesel_derive_enum::DbEnum, Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[ExistingTypePath = "crate::schema::sql_types::AvailabilityType"]
+pub enum AvailabilityTypeEnum {
+    available,
+    low,
+    sold_out,
+    not_on_sale,
+}
+
 #[allow(non_camel_case_types)]
 #[derive(diesel_derive_enum::DbEnum, Debug, Deserialize, Serialize)]
 #[ExistingTypePath = "crate::schema::sql_types::BundleType"]
@@ -30,6 +48,17 @@ pub enum LinkTypeEnum {
     demand,
 }
 
+#[allow(non_camel_case_types)]
+#[derive(diesel_derive_enum::DbEnum, Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[ExistingTypePath = "crate::schema::sql_types::MatchStatusType"]
+pub enum MatchStatusEnum {
+    matched,
+    fuzzy,
+    ambiguous,
+    unmatched,
+    resolved,
+}
+
 #[allow(non_camel_case_types)]
 #[derive(diesel_derive_enum::DbEnum, Debug, Deserialize, PartialEq, Serialize)]
 #[ExistingTypePath = "crate::schema::sql_types::RoleType"]
@@ -46,6 +75,29 @@ pub struct Circle {
     pub name: Option<String>,
     pub description: Option<String>,
     pub location: Option<String>,
+    pub booth_hall: Option<String>,
+    pub booth_block: Option<String>,
+    pub booth_space: Option<i32>,
+    pub booth_half: Option<String>,
+}
+
+#[derive(Queryable, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Event {
+    pub id: i32,
+    pub name: String,
+    pub venue: Option<String>,
+    pub start_date: SystemTime,
+    pub end_date: SystemTime,
+}
+
+#[derive(Queryable, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CircleEvent {
+    pub id: i32,
+    pub circle_id: i32,
+    pub event_id: i32,
+    pub location: Option<String>,
 }
 
 #[derive(Queryable, Serialize)]
@@ -57,6 +109,8 @@ pub struct Good {
     pub price: Option<i32>,
     pub category_id: i32,
     pub image_name: Option<String>,
+    pub stock: Option<i32>,
+    pub availability: AvailabilityTypeEnum,
 }
 
 #[derive(Serialize)]
@@ -69,16 +123,29 @@ pub struct FullGood {
     pub image_name: Option<String>,
     pub circle_id: i32,
     pub circle_name: Option<String>,
+    pub stock: Option<i32>,
+    pub availability: AvailabilityTypeEnum,
     pub category: Category,
     pub characters: Vec<CharacterWithReference>,
 }
 
-#[derive(Queryable, Serialize)]
+#[derive(Serialize)]
 #[serde(crate = "rocket::serde")]
 pub struct CharacterWithReference {
     pub id: i32,
     pub character: String,
     pub reference: String,
+    pub aliases: Vec<CharacterAlias>,
+}
+
+#[derive(Queryable, Serialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct CharacterAlias {
+    pub id: i32,
+    pub character_id: i32,
+    pub language: String,
+    pub kind: AliasKindEnum,
+    pub name: String,
 }
 
 #[derive(Queryable, Serialize)]
@@ -91,6 +158,8 @@ pub struct Bundle {
     #[serde(rename = "type")]
     pub type_: BundleTypeEnum,
     pub count: i32,
+    pub stock: Option<i32>,
+    pub availability: AvailabilityTypeEnum,
 }
 
 #[derive(Queryable, Serialize)]
@@ -100,6 +169,126 @@ pub struct BundleGoods {
     pub bundle_id: i32,
     pub goods_id: i32,
     pub count: i32,
+    pub weight: i32,
+}
+
+#[derive(Queryable, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BundleComponent {
+    pub goods_id: i32,
+    pub name: Option<String>,
+    pub price: Option<i32>,
+    pub count: i32,
+}
+
+impl BundleComponent {
+    pub fn separate_price(components: &[Self]) -> Option<i64> {
+        separate_price(
+            components
+                .iter()
+                .map(|component| (component.price, component.count)),
+        )
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BundleItem {
+    pub count: i32,
+    pub goods: FullGood,
+}
+
+/// Sums price times count over `(price, count)` pairs, or `None` if any of
+/// them has no price.
+fn separate_price(parts: impl Iterator<Item = (Option<i32>, i32)>) -> Option<i64> {
+    parts
+        .map(|(price, count)| price.map(|price| i64::from(price) * i64::from(count)))
+        .sum()
+}
+
+/// Something off about how a bundle is priced, for circles to fix.
+#[allow(non_camel_case_types)]
+#[derive(Debug, PartialEq, Serialize)]
+pub enum BundleIssue {
+    no_goods,
+    no_price,
+    negative_price,
+    component_without_price,
+    costs_more_than_separate,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct FullBundle {
+    pub id: i32,
+    pub name: Option<String>,
+    pub price: Option<i32>,
+    pub description: Option<String>,
+    #[serde(rename = "type")]
+    pub type_: BundleTypeEnum,
+    pub count: i32,
+    pub stock: Option<i32>,
+    pub availability: AvailabilityTypeEnum,
+    pub circle: Option<Circle>,
+    pub goods: Vec<BundleItem>,
+    /// Items in the bundle, counting every goods as many times as it is
+    /// included.
+    pub item_count: i64,
+    /// What the goods cost when bought one by one. Unknown while any of them
+    /// has no price.
+    pub separate_price: Option<i64>,
+    /// `separate_price` minus the bundle price; negative when the bundle is
+    /// the worse deal.
+    pub savings: Option<i64>,
+    pub issues: Vec<BundleIssue>,
+}
+
+impl FullBundle {
+    pub fn new(bundle: Bundle, circle: Option<Circle>, goods: Vec<BundleItem>) -> Self {
+        let item_count = goods.iter().map(|item| i64::from(item.count)).sum();
+        let separate_price =
+            separate_price(goods.iter().map(|item| (item.goods.price, item.count)));
+        let savings = separate_price
+            .zip(bundle.price)
+            .map(|(separate_price, price)| separate_price - i64::from(price));
+
+        let mut issues = Vec::new();
+
+        if goods.is_empty() {
+            issues.push(BundleIssue::no_goods);
+        }
+
+        match bundle.price {
+            None => issues.push(BundleIssue::no_price),
+            Some(price) if price < 0 => issues.push(BundleIssue::negative_price),
+            Some(_) => {}
+        }
+
+        if goods.iter().any(|item| item.goods.price.is_none()) {
+            issues.push(BundleIssue::component_without_price);
+        }
+
+        if savings.is_some_and(|savings| savings < 0) {
+            issues.push(BundleIssue::costs_more_than_separate);
+        }
+
+        FullBundle {
+            id: bundle.id,
+            name: bundle.name,
+            price: bundle.price,
+            description: bundle.description,
+            type_: bundle.type_,
+            count: bundle.count,
+            stock: bundle.stock,
+            availability: bundle.availability,
+            circle,
+            goods,
+            item_count,
+            separate_price,
+            savings,
+            issues,
+        }
+    }
 }
 
 #[derive(Queryable, Serialize)]
@@ -117,7 +306,7 @@ pub struct Ref {
     pub name: String,
 }
 
-#[derive(Queryable, Serialize)]
+#[derive(Queryable, Serialize, Clone)]
 #[serde(crate = "rocket::serde")]
 pub struct Category {
     pub id: i32,
@@ -143,6 +332,150 @@ pub struct Link {
     pub expire: Option<SystemTime>,
 }
 
+#[derive(Queryable, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Collection {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub share_token: Option<String>,
+}
+
+#[derive(Queryable, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct OrderForm {
+    pub id: i32,
+    pub circle_id: i32,
+    pub title: String,
+    pub description: Option<String>,
+    pub opens_at: SystemTime,
+    pub closes_at: SystemTime,
+}
+
+impl OrderForm {
+    pub fn is_open(&self) -> bool {
+        let now = SystemTime::now();
+
+        self.opens_at <= now && now < self.closes_at
+    }
+}
+
+#[derive(Queryable, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct OrderFormItem {
+    pub id: i32,
+    pub order_form_id: i32,
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub max_quantity: Option<i32>,
+}
+
+#[derive(Queryable, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Order {
+    pub id: i32,
+    pub order_form_id: i32,
+    pub user_id: i32,
+    pub note: Option<String>,
+    pub created_at: SystemTime,
+}
+
+#[derive(Queryable, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Sale {
+    pub id: i32,
+    pub circle_id: i32,
+    pub user_id: Option<i32>,
+    pub total: i32,
+    pub created_at: SystemTime,
+}
+
+#[derive(Queryable, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Survey {
+    pub id: i32,
+    pub circle_id: i32,
+    pub title: String,
+    pub description: Option<String>,
+    pub closes_at: SystemTime,
+}
+
+impl Survey {
+    pub fn is_open(&self) -> bool {
+        SystemTime::now() < self.closes_at
+    }
+}
+
+#[derive(Queryable, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SurveyItem {
+    pub id: i32,
+    pub survey_id: i32,
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+}
+
+#[derive(Queryable, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Prepayment {
+    pub id: i32,
+    pub circle_id: i32,
+    pub buyer_name: String,
+    pub contact: Option<String>,
+    pub note: Option<String>,
+    pub user_id: Option<i32>,
+    pub order_id: Option<i32>,
+    pub code: String,
+    pub created_at: SystemTime,
+    pub redeemed_at: Option<SystemTime>,
+    pub redeemed_by: Option<i32>,
+}
+
+#[derive(Queryable, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Reconciliation {
+    pub id: i32,
+    pub circle_id: i32,
+    pub title: String,
+    pub created_at: SystemTime,
+}
+
+#[derive(Queryable, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ReconciliationPayment {
+    pub id: i32,
+    pub reconciliation_id: i32,
+    pub depositor_name: String,
+    pub amount: i32,
+    pub note: Option<String>,
+}
+
+#[derive(Queryable, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ReconciliationDeposit {
+    pub id: i32,
+    pub reconciliation_id: i32,
+    pub line: i32,
+    pub depositor_name: String,
+    pub amount: i32,
+    pub deposited_at: Option<String>,
+    pub status: MatchStatusEnum,
+    pub payment_id: Option<i32>,
+    pub candidate_ids: Vec<i32>,
+}
+
+#[derive(Queryable, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Tally {
+    pub id: i32,
+    pub circle_id: i32,
+    pub event_id: Option<i32>,
+    pub title: String,
+    pub created_at: SystemTime,
+}
+
+#[allow(dead_code)]
 #[derive(Queryable)]
 pub struct UserSensitive {
     pub id: i32,
@@ -220,17 +553,18 @@ impl AuthenticatedUser {
     }
 }
 
-impl Into<User> for UserSensitive {
-    fn into(self) -> User {
+impl From<UserSensitive> for User {
+    fn from(user: UserSensitive) -> Self {
         User {
-            handle: self.handle,
-            nickname: self.nickname,
-            twitter_id: self.twitter_id,
-            role: self.role,
+            handle: user.handle,
+            nickname: user.nickname,
+            twitter_id: user.twitter_id,
+            role: user.role,
         }
     }
 }
 
+#[allow(dead_code)]
 #[derive(Queryable)]
 pub struct Token {
     pub id: i32,
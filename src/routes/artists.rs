@@ -1,6 +1,11 @@
 use crate::error_handler::{handle_error, CustomError, ErrorInfo};
 use crate::models::{Artist, AuthenticatedUser};
+use crate::schema::{artists, circle_artists};
+use crate::utils::search::name_matches;
+use crate::utils::pagination::{Page, PageRequest, Sort};
 use crate::DbPool;
+use diesel::dsl::{count_distinct, IntoBoxed, LeftJoinOn};
+use diesel::pg::Pg;
 use diesel::prelude::*;
 use rocket::http::Status;
 use rocket::response::status::{Created, Custom};
@@ -110,17 +115,17 @@ pub fn post_artist(
     Ok(Created::new(format!("/artists/{}", artist.id)).body(Json(artist)))
 }
 
-#[get("/artists?<circle_id>&<name>")]
-pub fn get_artists(
-    circle_id: Option<i32>,
-    name: Option<String>,
-    pool: &rocket::State<DbPool>,
-) -> Result<Json<Vec<Artist>>, CustomError> {
-    use crate::schema::artists;
-    use crate::schema::circle_artists;
-
-    let mut conn = pool.get().expect("Failed to get database connection");
-
+type ArtistsQuery = IntoBoxed<
+    'static,
+    LeftJoinOn<
+        artists::table,
+        circle_artists::table,
+        diesel::dsl::Eq<artists::id, circle_artists::artist_id>,
+    >,
+    Pg,
+>;
+
+fn artists_query(circle_id: Option<i32>, name: Option<&str>) -> ArtistsQuery {
     let mut query = artists::table
         .left_join(circle_artists::table.on(artists::id.eq(circle_artists::artist_id)))
         .into_boxed();
@@ -130,15 +135,58 @@ pub fn get_artists(
     }
 
     if let Some(name) = name {
-        query = query.filter(artists::name.similar_to(format!("%{}%", name)));
+        query = query.filter(name_matches(artists::name.nullable(), name));
     }
 
     query
+}
+
+#[get("/artists?<circle_id>&<name>&<limit>&<cursor>&<sort>")]
+pub fn get_artists(
+    circle_id: Option<i32>,
+    name: Option<String>,
+    limit: Option<i64>,
+    cursor: Option<String>,
+    sort: Option<String>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Page<Artist>>, CustomError> {
+    let sort = Sort::new(sort, "artists.id", &[("name", &["artists.name"])])?;
+    let page_request = PageRequest::new(limit, cursor.as_deref(), &sort)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let total = artists_query(circle_id, name.as_deref())
+        .select(count_distinct(artists::id))
+        .first::<i64>(&mut conn)
+        .map_err(handle_error)?;
+
+    let mut query = artists_query(circle_id, name.as_deref());
+
+    query = match (sort.key.as_str(), sort.descending) {
+        ("name", false) => query.order_by((artists::name.asc(), artists::id.asc())),
+        ("name", true) => query.order_by((artists::name.desc(), artists::id.desc())),
+        (_, false) => query.order_by(artists::id.asc()),
+        (_, true) => query.order_by(artists::id.desc()),
+    };
+
+    if let Some(after) = page_request.after() {
+        query = query.filter(after);
+    }
+
+    let artists = query
         .select(artists::all_columns)
         .distinct()
+        .limit(page_request.fetch_limit())
         .load::<Artist>(&mut conn)
-        .map(Json)
-        .map_err(handle_error)
+        .map_err(handle_error)?;
+
+    Ok(Json(page_request.page(artists, total, |artist| {
+        let key = match sort.key.as_str() {
+            "name" => vec![Some(artist.name.clone().into())],
+            _ => vec![],
+        };
+        (key, artist.id)
+    })))
 }
 
 #[get("/artists/<artist_id>")]
@@ -11,7 +11,6 @@ use diesel::{
 };
 use dotenvy::dotenv;
 use rand_core::OsRng;
-use reqwest;
 use rocket::{
     http::{Cookie, CookieJar, SameSite, Status},
     request::FromRequest,
@@ -54,9 +53,9 @@ impl NewUser {
     }
 }
 
-fn verify_password(password: &String, hash: &String) -> Result<(), argon2::password_hash::Error> {
+fn verify_password(password: &String, hash: &str) -> Result<(), argon2::password_hash::Error> {
     let argon2 = Argon2::default();
-    argon2.verify_password(password.as_bytes(), &PasswordHash::new(&hash)?)
+    argon2.verify_password(password.as_bytes(), &PasswordHash::new(hash)?)
 }
 
 fn is_handle_exists(
@@ -451,7 +450,7 @@ pub fn patch_me(
         )
     })?;
 
-    if let Some(_) = update_user.new_password {
+    if update_user.new_password.is_some() {
         diesel::delete(tokens::table)
             .filter(tokens::user_id.eq(user.id))
             .execute(&mut conn)
@@ -1,13 +1,27 @@
+use std::collections::HashSet;
+
 use crate::error_handler::{handle_error, CustomError, ErrorInfo};
-use crate::models::{AuthenticatedUser, Bundle, BundleGoods, BundleTypeEnum};
+use crate::models::{
+    AuthenticatedUser, Bundle, BundleComponent, BundleGoods, BundleItem, BundleTypeEnum, Circle,
+    FullBundle, Good,
+};
+use crate::routes::goods::{load_full_goods_with, UpdateStock};
+use crate::routes::stream::{ChangeAction, ChangeFeed, ChangeKind};
+use crate::schema::{bundles, circle_bundles};
+use crate::utils::odds::{self, Method};
+use crate::utils::pagination::{Page, PageRequest, Sort, SortValue};
 use crate::DbPool;
 
+use diesel::dsl::{count_distinct, IntoBoxed, LeftJoinOn};
+use diesel::pg::Pg;
 use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
 use rocket::http::Status;
 use rocket::response::status::{Created, Custom};
 use rocket::serde::json::Json;
 
 use rocket::serde::Deserialize;
+use serde::Serialize;
 
 #[derive(Queryable, Selectable, Insertable, Deserialize, AsChangeset)]
 #[diesel(table_name = crate::schema::bundles)]
@@ -38,11 +52,121 @@ pub struct NewCircleBundle {
     pub bundle_id: i32,
 }
 
+#[derive(Deserialize)]
+pub struct Selection {
+    pub goods: Vec<SelectedGoods>,
+}
+
+#[derive(Deserialize)]
+pub struct SelectedGoods {
+    pub goods_id: i32,
+    pub count: i32,
+}
+
+#[derive(Serialize)]
+pub struct BundleSelection {
+    pub bundle_id: i32,
+    pub goods: Vec<BundleComponent>,
+    pub price: Option<i32>,
+    pub separate_price: Option<i64>,
+    pub savings: Option<i64>,
+}
+
+fn load_components(
+    bundle_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<BundleComponent>, CustomError> {
+    use crate::schema::goods;
+    use crate::schema::goods_in_bundle;
+
+    goods_in_bundle::table
+        .inner_join(goods::table)
+        .filter(goods_in_bundle::bundle_id.eq(bundle_id))
+        .order(goods_in_bundle::id)
+        .select((
+            goods_in_bundle::goods_id,
+            goods::name,
+            goods::price,
+            goods_in_bundle::count,
+        ))
+        .load::<BundleComponent>(conn)
+        .map_err(handle_error)
+}
+
+/// How many items a buyer can pick from the bundle: the per-goods maxima in
+/// `goods_in_bundle.count` added up, leaving out `except_goods_id`. `None`
+/// when no goods are left.
+fn offered_items(
+    bundle_id: i32,
+    except_goods_id: Option<i32>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Option<i64>, CustomError> {
+    use crate::schema::goods_in_bundle;
+
+    let mut query = goods_in_bundle::table
+        .filter(goods_in_bundle::bundle_id.eq(bundle_id))
+        .into_boxed();
+
+    if let Some(except_goods_id) = except_goods_id {
+        query = query.filter(goods_in_bundle::goods_id.ne(except_goods_id));
+    }
+
+    query
+        .select(diesel::dsl::sum(goods_in_bundle::count))
+        .first::<Option<i64>>(conn)
+        .map_err(handle_error)
+}
+
+/// A `select` bundle asks buyers to pick `count` items, so its goods have to
+/// offer at least that many. Bundles without goods are still being set up
+/// and pass.
+fn check_selection_rules(
+    type_: &BundleTypeEnum,
+    count: i32,
+    offered: Option<i64>,
+) -> Result<(), CustomError> {
+    match offered {
+        Some(offered) if matches!(type_, BundleTypeEnum::select) && offered < i64::from(count) => {
+            Err(Custom(
+                Status::Conflict,
+                Json(ErrorInfo::new(format!(
+                    "Bundle offers only {} items to pick {} from",
+                    offered, count
+                ))),
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_count(count: i32) -> Result<(), CustomError> {
+    if count < 1 {
+        return Err(Custom(
+            Status::BadRequest,
+            Json(ErrorInfo::new("Count must be positive".into())),
+        ));
+    }
+
+    Ok(())
+}
+
+fn check_weight(weight: i32) -> Result<(), CustomError> {
+    if weight < 1 {
+        return Err(Custom(
+            Status::BadRequest,
+            Json(ErrorInfo::new("Weight must be positive".into())),
+        ));
+    }
+
+    Ok(())
+}
+
 #[post("/circles/<circle_id>/bundles", format = "json", data = "<new_bundle>")]
 pub fn post_circle_bundle(
     user: AuthenticatedUser,
     circle_id: i32,
     new_bundle: Json<NewBundle>,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<Created<Json<Bundle>>, CustomError> {
     use crate::schema::bundles;
@@ -50,6 +174,8 @@ pub fn post_circle_bundle(
 
     user.check_permission(circle_id)?;
 
+    check_count(new_bundle.count)?;
+
     let mut conn = pool.get().expect("Failed to get database connection");
 
     let bundle = diesel::insert_into(bundles::dsl::bundles)
@@ -65,33 +191,106 @@ pub fn post_circle_bundle(
         .execute(&mut conn)
         .map_err(handle_error)?;
 
+    feed.publish(ChangeKind::bundle, ChangeAction::created, bundle.id, circle_id);
+
     Ok(Created::new(format!("/bundles/{}", bundle.id)).body(Json(bundle)))
 }
 
-#[get("/bundles?<circle_id>")]
-pub fn get_bundles(
-    circle_id: Option<i32>,
-    pool: &rocket::State<DbPool>,
-) -> Result<Json<Vec<Bundle>>, CustomError> {
-    use crate::schema::bundles;
-    use crate::schema::circle_bundles;
+type BundlesQuery = IntoBoxed<
+    'static,
+    LeftJoinOn<
+        bundles::table,
+        circle_bundles::table,
+        diesel::dsl::Eq<bundles::id, circle_bundles::bundle_id>,
+    >,
+    Pg,
+>;
 
-    let mut conn = pool.get().expect("Failed to get database connection");
+fn bundles_query(circle_id: Option<i32>, event_id: Option<i32>) -> BundlesQuery {
+    use crate::schema::circle_events;
 
     let mut query = bundles::table
-        .left_join(circle_bundles::table.on(bundles::id.eq(circle_bundles::circle_id)))
+        .left_join(circle_bundles::table.on(bundles::id.eq(circle_bundles::bundle_id)))
         .into_boxed();
 
     if let Some(circle_id) = circle_id {
         query = query.filter(circle_bundles::circle_id.eq(circle_id));
     }
 
+    if let Some(event_id) = event_id {
+        let event_bundles = diesel::alias!(circle_bundles as event_bundles);
+
+        query = query.filter(
+            bundles::id.eq_any(
+                event_bundles
+                    .filter(
+                        event_bundles.field(circle_bundles::circle_id).eq_any(
+                            circle_events::table
+                                .filter(circle_events::event_id.eq(event_id))
+                                .select(circle_events::circle_id),
+                        ),
+                    )
+                    .select(event_bundles.field(circle_bundles::bundle_id)),
+            ),
+        );
+    }
+
     query
+}
+
+#[get("/bundles?<circle_id>&<event_id>&<limit>&<cursor>&<sort>")]
+pub fn get_bundles(
+    circle_id: Option<i32>,
+    event_id: Option<i32>,
+    limit: Option<i64>,
+    cursor: Option<String>,
+    sort: Option<String>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Page<Bundle>>, CustomError> {
+    let sort = Sort::new(
+        sort,
+        "bundles.id",
+        &[("name", &["bundles.name"]), ("price", &["bundles.price"])],
+    )?;
+    let page_request = PageRequest::new(limit, cursor.as_deref(), &sort)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let total = bundles_query(circle_id, event_id)
+        .select(count_distinct(bundles::id))
+        .first::<i64>(&mut conn)
+        .map_err(handle_error)?;
+
+    let mut query = bundles_query(circle_id, event_id);
+
+    query = match (sort.key.as_str(), sort.descending) {
+        ("name", false) => query.order_by((bundles::name.asc(), bundles::id.asc())),
+        ("name", true) => query.order_by((bundles::name.desc(), bundles::id.desc())),
+        ("price", false) => query.order_by((bundles::price.asc(), bundles::id.asc())),
+        ("price", true) => query.order_by((bundles::price.desc(), bundles::id.desc())),
+        (_, false) => query.order_by(bundles::id.asc()),
+        (_, true) => query.order_by(bundles::id.desc()),
+    };
+
+    if let Some(after) = page_request.after() {
+        query = query.filter(after);
+    }
+
+    let bundles = query
         .select(bundles::all_columns)
         .distinct()
+        .limit(page_request.fetch_limit())
         .load::<Bundle>(&mut conn)
-        .map(Json)
-        .map_err(handle_error)
+        .map_err(handle_error)?;
+
+    Ok(Json(page_request.page(bundles, total, |bundle| {
+        let key = match sort.key.as_str() {
+            "name" => vec![bundle.name.clone().map(SortValue::from)],
+            "price" => vec![bundle.price.map(SortValue::from)],
+            _ => vec![],
+        };
+        (key, bundle.id)
+    })))
 }
 
 #[get("/bundles/<bundle_id>")]
@@ -112,11 +311,347 @@ pub fn get_bundle_by_id(
     Ok(bundle)
 }
 
+/// The bundle with its circle and goods, and how its price compares to
+/// buying those goods separately. Takes the same number of queries however
+/// many goods the bundle has.
+#[get("/bundles/<bundle_id>/full")]
+pub fn get_full_bundle(
+    bundle_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<FullBundle>, CustomError> {
+    use crate::schema::circles;
+    use crate::schema::goods;
+    use crate::schema::goods_in_bundle;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let bundle = bundles::table
+        .find(bundle_id)
+        .first::<Bundle>(&mut conn)
+        .map_err(handle_error)?;
+
+    let circle = circle_bundles::table
+        .inner_join(circles::table)
+        .filter(circle_bundles::bundle_id.eq(bundle_id))
+        .select(circles::all_columns)
+        .first::<Circle>(&mut conn)
+        .optional()
+        .map_err(handle_error)?;
+
+    let goods = goods_in_bundle::table
+        .inner_join(goods::table)
+        .filter(goods_in_bundle::bundle_id.eq(bundle_id))
+        .order(goods_in_bundle::id)
+        .select((goods_in_bundle::count, goods::all_columns))
+        .load::<(i32, Good)>(&mut conn)
+        .map_err(handle_error)?;
+
+    let items = load_full_goods_with(goods, &mut conn)?
+        .into_iter()
+        .map(|(count, goods)| BundleItem { count, goods })
+        .collect();
+
+    Ok(Json(FullBundle::new(bundle, circle, items)))
+}
+
+/// Checks a buyer's pick for a `select` bundle: exactly `count` items in
+/// total, each goods at most as many times as the bundle allows.
+#[post(
+    "/bundles/<bundle_id>/selection",
+    format = "json",
+    data = "<selection>"
+)]
+pub fn post_bundle_selection(
+    bundle_id: i32,
+    selection: Json<Selection>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<BundleSelection>, CustomError> {
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let bundle = bundles::table
+        .find(bundle_id)
+        .first::<Bundle>(&mut conn)
+        .map_err(handle_error)?;
+
+    let invalid = |message: String| Custom(Status::BadRequest, Json(ErrorInfo::new(message)));
+
+    if !matches!(bundle.type_, BundleTypeEnum::select) {
+        return Err(invalid("Only select bundles take a selection".into()));
+    }
+
+    let mut components = load_components(bundle_id, &mut conn)?;
+    let mut picked = Vec::new();
+    let mut seen = HashSet::new();
+
+    for pick in selection.into_inner().goods {
+        check_count(pick.count)?;
+
+        if !seen.insert(pick.goods_id) {
+            return Err(invalid("Item is listed twice".into()));
+        }
+
+        let index = components
+            .iter()
+            .position(|component| component.goods_id == pick.goods_id)
+            .ok_or_else(|| invalid(format!("Goods {} is not in this bundle", pick.goods_id)))?;
+        let mut component = components.swap_remove(index);
+
+        if pick.count > component.count {
+            return Err(invalid(format!(
+                "At most {} of goods {} can be picked",
+                component.count, pick.goods_id
+            )));
+        }
+
+        component.count = pick.count;
+        picked.push(component);
+    }
+
+    let total = picked.iter().map(|component| component.count).sum::<i32>();
+
+    if total != bundle.count {
+        return Err(invalid(format!(
+            "Pick exactly {} items, not {}",
+            bundle.count, total
+        )));
+    }
+
+    let separate_price = BundleComponent::separate_price(&picked);
+
+    Ok(Json(BundleSelection {
+        bundle_id,
+        goods: picked,
+        price: bundle.price,
+        separate_price,
+        savings: separate_price
+            .zip(bundle.price)
+            .map(|(separate_price, price)| separate_price - i64::from(price)),
+    }))
+}
+
+#[derive(Serialize)]
+pub struct GoodsOdds {
+    pub goods_id: i32,
+    pub name: Option<String>,
+    pub weight: i32,
+    /// Chance that any one item in a pack is this goods.
+    pub probability: f64,
+    /// Chance that a pack holds at least one of this goods.
+    pub chance_per_pack: f64,
+}
+
+#[derive(Serialize)]
+pub struct BundleOdds {
+    pub bundle_id: i32,
+    pub count: i32,
+    pub price: Option<i32>,
+    pub goods: Vec<GoodsOdds>,
+}
+
+#[derive(Serialize)]
+pub struct SetCompletion {
+    pub bundle_id: i32,
+    pub goods_ids: Vec<i32>,
+    pub expected_packs: f64,
+    pub expected_cost: Option<f64>,
+    /// `exact`, or `simulation` when there are too many goods to work it out.
+    pub method: String,
+    pub trials: Option<u32>,
+}
+
+#[derive(Queryable)]
+struct WeightedGoods {
+    goods_id: i32,
+    name: Option<String>,
+    weight: i32,
+}
+
+/// Loads a random bundle with the goods it can be drawn from, ordered like
+/// the bundle's goods.
+fn load_random_bundle(
+    bundle_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<(Bundle, Vec<WeightedGoods>), CustomError> {
+    use crate::schema::goods;
+    use crate::schema::goods_in_bundle;
+
+    let bundle = bundles::table
+        .find(bundle_id)
+        .first::<Bundle>(conn)
+        .map_err(handle_error)?;
+
+    if !matches!(bundle.type_, BundleTypeEnum::random) {
+        return Err(Custom(
+            Status::BadRequest,
+            Json(ErrorInfo::new("Only random bundles have odds".into())),
+        ));
+    }
+
+    let goods = goods_in_bundle::table
+        .inner_join(goods::table)
+        .filter(goods_in_bundle::bundle_id.eq(bundle_id))
+        .order(goods_in_bundle::id)
+        .select((
+            goods_in_bundle::goods_id,
+            goods::name,
+            goods_in_bundle::weight,
+        ))
+        .load::<WeightedGoods>(conn)
+        .map_err(handle_error)?;
+
+    if goods.is_empty() || bundle.count < 1 {
+        return Err(Custom(
+            Status::Conflict,
+            Json(ErrorInfo::new("Bundle has nothing to draw".into())),
+        ));
+    }
+
+    Ok((bundle, goods))
+}
+
+/// The chance of drawing each goods from a random bundle. Every item in a
+/// pack is drawn on its own, weighted by `goods_in_bundle.weight`.
+#[get("/bundles/<bundle_id>/odds")]
+pub fn get_bundle_odds(
+    bundle_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<BundleOdds>, CustomError> {
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let (bundle, goods) = load_random_bundle(bundle_id, &mut conn)?;
+
+    let total = goods
+        .iter()
+        .map(|goods| f64::from(goods.weight))
+        .sum::<f64>();
+
+    Ok(Json(BundleOdds {
+        bundle_id,
+        count: bundle.count,
+        price: bundle.price,
+        goods: goods
+            .into_iter()
+            .map(|goods| {
+                let probability = f64::from(goods.weight) / total;
+
+                GoodsOdds {
+                    goods_id: goods.goods_id,
+                    name: goods.name,
+                    weight: goods.weight,
+                    probability,
+                    chance_per_pack: 1.0 - (1.0 - probability).powi(bundle.count),
+                }
+            })
+            .collect(),
+    }))
+}
+
+/// How many packs of a random bundle it takes on average to collect every
+/// goods in `goods_id`, or the whole set when none are given.
+///
+/// Large sets are simulated, so the work runs on the blocking pool rather
+/// than on a worker.
+#[get("/bundles/<bundle_id>/odds/completion?<goods_id>")]
+pub async fn get_bundle_completion(
+    bundle_id: i32,
+    goods_id: Vec<i32>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<SetCompletion>, CustomError> {
+    let pool = pool.inner().clone();
+
+    rocket::tokio::task::spawn_blocking(move || bundle_completion(bundle_id, goods_id, &pool))
+        .await
+        .map_err(|_| {
+            Custom(
+                Status::InternalServerError,
+                Json(ErrorInfo::new("internal_server_error".to_string())),
+            )
+        })?
+        .map(Json)
+}
+
+fn bundle_completion(
+    bundle_id: i32,
+    goods_id: Vec<i32>,
+    pool: &DbPool,
+) -> Result<SetCompletion, CustomError> {
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let (bundle, goods) = load_random_bundle(bundle_id, &mut conn)?;
+
+    if bundle.count as u32 > odds::MAX_PER_PACK {
+        return Err(Custom(
+            Status::UnprocessableEntity,
+            Json(ErrorInfo::new(format!(
+                "Odds are only worked out for packs of up to {} items",
+                odds::MAX_PER_PACK
+            ))),
+        ));
+    }
+
+    let goods_ids = if goods_id.is_empty() {
+        goods.iter().map(|goods| goods.goods_id).collect()
+    } else {
+        let mut goods_ids = goods_id;
+        goods_ids.sort_unstable();
+        goods_ids.dedup();
+        goods_ids
+    };
+
+    let wanted = goods_ids
+        .iter()
+        .map(|wanted_id| {
+            goods
+                .iter()
+                .position(|goods| goods.goods_id == *wanted_id)
+                .ok_or_else(|| {
+                    Custom(
+                        Status::BadRequest,
+                        Json(ErrorInfo::new(format!(
+                            "Goods {} is not in this bundle",
+                            wanted_id
+                        ))),
+                    )
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let weights = goods
+        .iter()
+        .map(|goods| goods.weight as u32)
+        .collect::<Vec<_>>();
+
+    let (expected_packs, method) = odds::expected_packs(&weights, &wanted, bundle.count as u32)
+        .ok_or_else(|| {
+            Custom(
+                Status::UnprocessableEntity,
+                Json(ErrorInfo::new(
+                    "Weights are too uneven to estimate the odds".into(),
+                )),
+            )
+        })?;
+
+    let (method, trials) = match method {
+        Method::Exact => ("exact", None),
+        Method::Simulation { trials } => ("simulation", Some(trials)),
+    };
+
+    Ok(SetCompletion {
+        bundle_id,
+        goods_ids,
+        expected_packs,
+        expected_cost: bundle.price.map(|price| expected_packs * f64::from(price)),
+        method: method.to_string(),
+        trials,
+    })
+}
+
 #[patch("/bundles/<bundle_id>", format = "json", data = "<update_bundle>")]
 pub fn patch_bundle(
     user: AuthenticatedUser,
     bundle_id: i32,
     update_bundle: Json<UpdateBundle>,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<Json<Bundle>, CustomError> {
     use crate::schema::bundles::dsl::*;
@@ -132,11 +667,28 @@ pub fn patch_bundle(
 
     user.check_permission(circle_id)?;
 
+    let current = bundles
+        .find(bundle_id)
+        .first::<Bundle>(&mut conn)
+        .map_err(handle_error)?;
+
+    if let Some(new_count) = update_bundle.count {
+        check_count(new_count)?;
+    }
+
+    check_selection_rules(
+        update_bundle.type_.as_ref().unwrap_or(&current.type_),
+        update_bundle.count.unwrap_or(current.count),
+        offered_items(bundle_id, None, &mut conn)?,
+    )?;
+
     diesel::update(bundles.find(bundle_id))
         .set(update_bundle.into_inner())
         .execute(&mut conn)
         .map_err(handle_error)?;
 
+    feed.publish(ChangeKind::bundle, ChangeAction::updated, bundle_id, circle_id);
+
     bundles
         .find(bundle_id)
         .first(&mut conn)
@@ -144,10 +696,44 @@ pub fn patch_bundle(
         .map_err(handle_error)
 }
 
+#[patch("/bundles/<bundle_id>/stock", format = "json", data = "<update_stock>")]
+pub fn patch_bundle_stock(
+    user: AuthenticatedUser,
+    bundle_id: i32,
+    update_stock: Json<UpdateStock>,
+    feed: &rocket::State<ChangeFeed>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Bundle>, CustomError> {
+    let (stock, availability) = update_stock.into_inner().resolve()?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let circle_id = circle_bundles::table
+        .filter(circle_bundles::bundle_id.eq(bundle_id))
+        .select(circle_bundles::circle_id)
+        .first::<i32>(&mut conn)
+        .map_err(handle_error)?;
+
+    user.check_permission(circle_id)?;
+
+    let bundle = diesel::update(bundles::table.find(bundle_id))
+        .set((
+            stock.map(|stock| bundles::stock.eq(stock)),
+            availability.map(|availability| bundles::availability.eq(availability)),
+        ))
+        .get_result::<Bundle>(&mut conn)
+        .map_err(handle_error)?;
+
+    feed.publish(ChangeKind::bundle, ChangeAction::updated, bundle_id, circle_id);
+
+    Ok(Json(bundle))
+}
+
 #[delete("/bundles/<bundle_id>")]
 pub fn delete_bundle(
     user: AuthenticatedUser,
     bundle_id: i32,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<(), CustomError> {
     use crate::schema::bundles::dsl::*;
@@ -179,6 +765,8 @@ pub fn delete_bundle(
             Json(ErrorInfo::new("not_found".to_string())),
         ))
     } else {
+        feed.publish(ChangeKind::bundle, ChangeAction::deleted, bundle_id, circle_id);
+
         Ok(())
     }
 }
@@ -188,6 +776,7 @@ pub fn delete_bundle(
 pub struct NewGoodId {
     pub goods_id: i32,
     pub count: i32,
+    pub weight: Option<i32>,
 }
 
 #[derive(Insertable)]
@@ -196,12 +785,42 @@ pub struct NewBundleGoods {
     pub bundle_id: i32,
     pub goods_id: i32,
     pub count: i32,
+    pub weight: Option<i32>,
+}
+
+/// A bundle can only hold goods sold by the circle that owns it.
+fn check_same_circle(
+    circle_id: i32,
+    goods_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<(), CustomError> {
+    use crate::schema::circle_goods;
+
+    let owned = circle_goods::table
+        .filter(circle_goods::circle_id.eq(circle_id))
+        .filter(circle_goods::goods_id.eq(goods_id))
+        .count()
+        .get_result::<i64>(conn)
+        .map_err(handle_error)?;
+
+    if owned == 0 {
+        return Err(Custom(
+            Status::Conflict,
+            Json(ErrorInfo::new(format!(
+                "Goods {} does not belong to the bundle's circle",
+                goods_id
+            ))),
+        ));
+    }
+
+    Ok(())
 }
 
 #[derive(Queryable, Selectable, Insertable, Deserialize, AsChangeset)]
 #[diesel(table_name = crate::schema::goods_in_bundle)]
 pub struct UpdateBundleGoods {
     pub count: Option<i32>,
+    pub weight: Option<i32>,
 }
 
 #[post("/bundles/<bundle_id>/goods", format = "json", data = "<new_goods>")]
@@ -209,6 +828,7 @@ pub fn post_bundle_goods(
     user: AuthenticatedUser,
     bundle_id: i32,
     new_goods: Json<NewGoodId>,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<Created<()>, CustomError> {
     use crate::schema::circle_bundles;
@@ -224,15 +844,26 @@ pub fn post_bundle_goods(
 
     user.check_permission(circle_id)?;
 
+    check_count(new_goods.count)?;
+
+    if let Some(weight) = new_goods.weight {
+        check_weight(weight)?;
+    }
+
+    check_same_circle(circle_id, new_goods.goods_id, &mut conn)?;
+
     diesel::insert_into(goods_in_bundle::dsl::goods_in_bundle)
         .values(NewBundleGoods {
             bundle_id,
             goods_id: new_goods.goods_id,
             count: new_goods.count,
+            weight: new_goods.weight,
         })
         .execute(&mut conn)
         .map_err(handle_error)?;
 
+    feed.publish(ChangeKind::bundle, ChangeAction::updated, bundle_id, circle_id);
+
     Ok(Created::new(format!(
         "/bundles/{}/goods/{}",
         bundle_id, new_goods.goods_id
@@ -249,6 +880,7 @@ pub fn patch_bundle_goods(
     bundle_id: i32,
     goods_id: i32,
     update_bundle_goods: Json<UpdateBundleGoods>,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<Json<BundleGoods>, CustomError> {
     use crate::schema::circle_bundles;
@@ -264,6 +896,26 @@ pub fn patch_bundle_goods(
 
     user.check_permission(circle_id)?;
 
+    if let Some(weight) = update_bundle_goods.weight {
+        check_weight(weight)?;
+    }
+
+    if let Some(count) = update_bundle_goods.count {
+        check_count(count)?;
+
+        let bundle = bundles::table
+            .find(bundle_id)
+            .first::<Bundle>(&mut conn)
+            .map_err(handle_error)?;
+        let offered = offered_items(bundle_id, Some(goods_id), &mut conn)?;
+
+        check_selection_rules(
+            &bundle.type_,
+            bundle.count,
+            Some(offered.unwrap_or(0) + i64::from(count)),
+        )?;
+    }
+
     diesel::update(
         goods_in_bundle::dsl::goods_in_bundle
             .filter(goods_in_bundle::dsl::bundle_id.eq(bundle_id))
@@ -279,6 +931,8 @@ pub fn patch_bundle_goods(
         .first(&mut conn)
         .map_err(handle_error)?;
 
+    feed.publish(ChangeKind::bundle, ChangeAction::updated, bundle_id, circle_id);
+
     Ok(Json(updated_goods_in_bundle))
 }
 
@@ -287,6 +941,7 @@ pub fn delete_bundle_goods(
     user: AuthenticatedUser,
     bundle_id: i32,
     goods_id: i32,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<(), CustomError> {
     use crate::schema::circle_bundles;
@@ -302,6 +957,17 @@ pub fn delete_bundle_goods(
 
     user.check_permission(circle_id)?;
 
+    let bundle = bundles::table
+        .find(bundle_id)
+        .first::<Bundle>(&mut conn)
+        .map_err(handle_error)?;
+
+    check_selection_rules(
+        &bundle.type_,
+        bundle.count,
+        offered_items(bundle_id, Some(goods_id), &mut conn)?,
+    )?;
+
     let size = diesel::delete(
         goods_in_bundle::dsl::goods_in_bundle
             .filter(goods_in_bundle::dsl::bundle_id.eq(bundle_id))
@@ -316,6 +982,109 @@ pub fn delete_bundle_goods(
             Json(ErrorInfo::new("not_found".to_string())),
         ))
     } else {
+        feed.publish(ChangeKind::bundle, ChangeAction::updated, bundle_id, circle_id);
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::goods_in_bundle;
+    use crate::test_support::{
+        insert_bundle, insert_category, insert_character, insert_circle, insert_goods,
+        moderator, test_connection, test_pool,
+    };
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn bundles_are_filtered_by_their_own_circle() {
+        let mut conn = test_connection();
+        let circle_id = insert_circle(&mut conn);
+        let other_circle_id = insert_circle(&mut conn);
+        let first = insert_bundle(&mut conn, circle_id);
+        let second = insert_bundle(&mut conn, circle_id);
+        insert_bundle(&mut conn, other_circle_id);
+
+        let found = bundles_query(Some(circle_id), None)
+            .select(bundles::id)
+            .order(bundles::id)
+            .load::<i32>(&mut conn)
+            .unwrap();
+
+        assert_eq!(found, vec![first, second]);
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn bundles_take_only_goods_of_their_own_circle() {
+        let pool = test_pool();
+        let feed = ChangeFeed::default();
+
+        let (bundle_id, own, other) = {
+            let mut conn = pool.get().unwrap();
+            let circle_id = insert_circle(&mut conn);
+            let other_circle_id = insert_circle(&mut conn);
+            let category_id = insert_category(&mut conn);
+            let character_id = insert_character(&mut conn);
+
+            (
+                insert_bundle(&mut conn, circle_id),
+                insert_goods(&mut conn, 1, circle_id, category_id, character_id)[0],
+                insert_goods(&mut conn, 1, other_circle_id, category_id, character_id)[0],
+            )
+        };
+
+        let add = |goods_id| {
+            post_bundle_goods(
+                moderator(),
+                bundle_id,
+                Json(NewGoodId {
+                    goods_id,
+                    count: 1,
+                    weight: None,
+                }),
+                (&feed).into(),
+                (&pool).into(),
+            )
+        };
+
+        assert!(add(own).is_ok());
+
+        let Err(Custom(status, _)) = add(other) else {
+            panic!("goods of another circle were added");
+        };
+        assert_eq!(status, Status::Conflict);
+
+        let contents = goods_in_bundle::table
+            .filter(goods_in_bundle::bundle_id.eq(bundle_id))
+            .select(goods_in_bundle::goods_id)
+            .load::<i32>(&mut pool.get().unwrap())
+            .unwrap();
+        assert_eq!(contents, vec![own]);
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn bundles_belong_to_a_single_circle() {
+        let mut conn = test_connection();
+        let circle_id = insert_circle(&mut conn);
+        let other_circle_id = insert_circle(&mut conn);
+        let bundle_id = insert_bundle(&mut conn, circle_id);
+
+        let linked = conn.transaction(|conn| {
+            diesel::insert_into(circle_bundles::table)
+                .values(NewCircleBundle {
+                    circle_id: other_circle_id,
+                    bundle_id,
+                })
+                .execute(conn)
+        });
+
+        let Err(Custom(status, _)) = linked.map_err(handle_error) else {
+            panic!("a bundle was linked to a second circle");
+        };
+        assert_eq!(status, Status::Conflict);
+    }
+}
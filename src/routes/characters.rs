@@ -1,8 +1,17 @@
+use std::collections::HashMap;
+
 use crate::error_handler::{handle_error, CustomError, ErrorInfo};
-use crate::models::{AuthenticatedUser, Character, CharacterWithReference};
+use crate::models::{
+    AliasKindEnum, AuthenticatedUser, Character, CharacterAlias, CharacterWithReference,
+};
+use crate::schema::{character_aliases, characters, refs};
+use crate::utils::search::{name_matches, search_key};
+use crate::utils::pagination::{Page, PageRequest, Sort};
 use crate::DbPool;
-use diesel::dsl::sql;
+use diesel::dsl::{count_distinct, sql, IntoBoxed, LeftJoinOn};
+use diesel::pg::Pg;
 use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
 use diesel::sql_types::{Integer, Text};
 use rocket::http::Status;
 use rocket::response::status::{Created, Custom};
@@ -24,6 +33,63 @@ pub struct UpdateCharacter {
     pub reference_id: Option<i32>,
 }
 
+#[derive(Deserialize, Insertable)]
+#[diesel(table_name = crate::schema::character_aliases)]
+pub struct NewCharacterAlias {
+    pub language: String,
+    pub kind: AliasKindEnum,
+    pub name: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::character_aliases)]
+pub struct InsertCharacterAlias {
+    pub character_id: i32,
+    pub language: String,
+    pub kind: AliasKindEnum,
+    pub name: String,
+}
+
+/// Ids of the characters whose name or any of whose aliases matches `name`.
+pub(crate) fn characters_named(name: &str) -> characters::BoxedQuery<'static, Pg, Integer> {
+    characters::table
+        .filter(
+            name_matches(characters::name.nullable(), name).or(characters::id
+                .eq_any(
+                    character_aliases::table
+                        .filter(name_matches(character_aliases::name.nullable(), name))
+                        .select(character_aliases::character_id),
+                )
+                .nullable()),
+        )
+        .select(characters::id)
+        .into_boxed()
+}
+
+/// Loads the aliases of every character in `character_ids` with one query,
+/// keyed by character.
+pub(crate) fn load_aliases(
+    character_ids: &[i32],
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<HashMap<i32, Vec<CharacterAlias>>, CustomError> {
+    let mut aliases: HashMap<i32, Vec<CharacterAlias>> = HashMap::new();
+
+    for alias in character_aliases::table
+        .filter(character_aliases::character_id.eq_any(character_ids))
+        .order((
+            character_aliases::language,
+            character_aliases::kind,
+            character_aliases::id,
+        ))
+        .load::<CharacterAlias>(conn)
+        .map_err(handle_error)?
+    {
+        aliases.entry(alias.character_id).or_default().push(alias);
+    }
+
+    Ok(aliases)
+}
+
 #[post("/characters", format = "json", data = "<new_character>")]
 pub fn post_character(
     user: AuthenticatedUser,
@@ -44,24 +110,23 @@ pub fn post_character(
     Ok(Created::new(format!("/characters/{}", character.id)).body(Json(character)))
 }
 
-#[get("/characters?<name>&<ref_id>&<ref_name>")]
-pub fn get_characters(
-    name: Option<String>,
-    ref_id: Option<i32>,
-    ref_name: Option<String>,
-    pool: &rocket::State<DbPool>,
-) -> Result<Json<Vec<CharacterWithReference>>, CustomError> {
-    use crate::schema::characters;
-    use crate::schema::refs;
-
-    let mut conn = pool.get().expect("Failed to get database connection");
+type CharactersQuery = IntoBoxed<
+    'static,
+    LeftJoinOn<characters::table, refs::table, diesel::dsl::Eq<characters::reference_id, refs::id>>,
+    Pg,
+>;
 
+fn characters_query(
+    name: Option<&str>,
+    ref_id: Option<i32>,
+    ref_name: Option<&str>,
+) -> CharactersQuery {
     let mut query = characters::table
         .left_join(refs::table.on(characters::reference_id.eq(refs::id)))
         .into_boxed();
 
     if let Some(name) = name {
-        query = query.filter(characters::name.similar_to(format!("%{}%", name)));
+        query = query.filter(characters::id.eq_any(characters_named(name)));
     }
 
     if let Some(ref_id) = ref_id {
@@ -69,15 +134,76 @@ pub fn get_characters(
     }
 
     if let Some(ref_name) = ref_name {
-        query = query.filter(refs::name.eq(ref_name));
+        query = query
+            .filter(search_key(refs::name.nullable()).eq(search_key(ref_name.to_string())));
     }
 
     query
+}
+
+#[get("/characters?<name>&<ref_id>&<ref_name>&<limit>&<cursor>&<sort>")]
+pub fn get_characters(
+    name: Option<String>,
+    ref_id: Option<i32>,
+    ref_name: Option<String>,
+    limit: Option<i64>,
+    cursor: Option<String>,
+    sort: Option<String>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Page<CharacterWithReference>>, CustomError> {
+    let sort = Sort::new(sort, "characters.id", &[("name", &["characters.name"])])?;
+    let page_request = PageRequest::new(limit, cursor.as_deref(), &sort)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let total = characters_query(name.as_deref(), ref_id, ref_name.as_deref())
+        .select(count_distinct(characters::id))
+        .first::<i64>(&mut conn)
+        .map_err(handle_error)?;
+
+    let mut query = characters_query(name.as_deref(), ref_id, ref_name.as_deref());
+
+    query = match (sort.key.as_str(), sort.descending) {
+        ("name", false) => query.order_by((characters::name.asc(), characters::id.asc())),
+        ("name", true) => query.order_by((characters::name.desc(), characters::id.desc())),
+        (_, false) => query.order_by(characters::id.asc()),
+        (_, true) => query.order_by(characters::id.desc()),
+    };
+
+    if let Some(after) = page_request.after() {
+        query = query.filter(after);
+    }
+
+    let characters = query
         .select((sql::<Integer>("characters.id"), sql::<Text>("characters.name"), sql::<Text>("refs.name")))
         .distinct()
-        .load::<CharacterWithReference>(&mut conn)
-        .map(Json)
-        .map_err(handle_error)
+        .limit(page_request.fetch_limit())
+        .load::<(i32, String, String)>(&mut conn)
+        .map_err(handle_error)?;
+
+    let page = page_request.page(characters, total, |(id, character, _)| {
+        let key = match sort.key.as_str() {
+            "name" => vec![Some(character.clone().into())],
+            _ => vec![],
+        };
+        (key, *id)
+    });
+
+    page.try_map(|characters| {
+        let character_ids = characters.iter().map(|(id, _, _)| *id).collect::<Vec<_>>();
+        let mut aliases = load_aliases(&character_ids, &mut conn)?;
+
+        Ok(characters
+            .into_iter()
+            .map(|(id, character, reference)| CharacterWithReference {
+                aliases: aliases.remove(&id).unwrap_or_default(),
+                id,
+                character,
+                reference,
+            })
+            .collect())
+    })
+    .map(Json)
 }
 
 #[get("/characters/<character_id>")]
@@ -150,3 +276,74 @@ pub fn delete_character(
         Ok(())
     }
 }
+
+#[post(
+    "/characters/<character_id>/aliases",
+    format = "json",
+    data = "<new_alias>"
+)]
+pub fn post_character_alias(
+    user: AuthenticatedUser,
+    character_id: i32,
+    new_alias: Json<NewCharacterAlias>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<Json<CharacterAlias>>, CustomError> {
+    user.check_artist()?;
+
+    let new_alias = new_alias.into_inner();
+    let language = new_alias.language.trim().to_lowercase();
+    let name = new_alias.name.trim().to_string();
+
+    if language.is_empty() || name.is_empty() {
+        return Err(Custom(
+            Status::BadRequest,
+            Json(ErrorInfo::new("Language and name must be given".into())),
+        ));
+    }
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let alias = diesel::insert_into(character_aliases::table)
+        .values(InsertCharacterAlias {
+            character_id,
+            language,
+            kind: new_alias.kind,
+            name,
+        })
+        .get_result::<CharacterAlias>(&mut conn)
+        .map_err(handle_error)?;
+
+    Ok(
+        Created::new(format!("/characters/{}/aliases/{}", character_id, alias.id))
+            .body(Json(alias)),
+    )
+}
+
+#[delete("/characters/<character_id>/aliases/<alias_id>")]
+pub fn delete_character_alias(
+    user: AuthenticatedUser,
+    character_id: i32,
+    alias_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    user.check_moderator()?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let size = diesel::delete(
+        character_aliases::table
+            .find(alias_id)
+            .filter(character_aliases::character_id.eq(character_id)),
+    )
+    .execute(&mut conn)
+    .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
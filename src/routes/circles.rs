@@ -3,10 +3,16 @@ use std::time::SystemTime;
 
 use crate::error_handler::{handle_error, CustomError, ErrorInfo};
 use crate::models::{AuthenticatedUser, LinkTypeEnum, Link};
+use crate::routes::stream::{ChangeAction, ChangeFeed, ChangeKind};
 use crate::schema::sql_types::LinkType;
+use crate::schema::{artists, circle_artists, circles};
+use crate::utils::booth::Booth;
+use crate::utils::search::name_matches;
+use crate::utils::pagination::{Page, PageRequest, Sort, SortValue};
 use crate::{models::Circle, DbPool};
 
-use diesel::dsl::sql;
+use diesel::dsl::{count_distinct, sql, IntoBoxed, LeftJoinOn};
+use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::sql_types::{Integer, Text, Nullable, Timestamp};
 use rocket::http::Status;
@@ -15,42 +21,198 @@ use rocket::serde::json::Json;
 use rocket::serde::Deserialize;
 use serde::Serialize;
 
-#[get("/circles?<name>&<artist_name>&<location>")]
-pub fn get_circles(
+pub(crate) fn parse_booth(location: &str) -> Result<Booth, CustomError> {
+    location.parse::<Booth>().map_err(|_| {
+        Custom(
+            Status::BadRequest,
+            Json(ErrorInfo::new("Invalid booth location".into())),
+        )
+    })
+}
+
+type CirclesQuery = IntoBoxed<
+    'static,
+    LeftJoinOn<
+        LeftJoinOn<
+            circles::table,
+            circle_artists::table,
+            diesel::dsl::Eq<circles::id, circle_artists::circle_id>,
+        >,
+        artists::table,
+        diesel::dsl::Eq<circle_artists::artist_id, artists::id>,
+    >,
+    Pg,
+>;
+
+struct CirclesFilter {
     name: Option<String>,
     artist_name: Option<String>,
-    location: Option<String>,
-    pool: &rocket::State<DbPool>,
-) -> Result<Json<Vec<Circle>>, CustomError> {
-    use crate::schema::artists;
-    use crate::schema::circle_artists;
-    use crate::schema::circles;
+    booth: Option<Booth>,
+    event_id: Option<i32>,
+    hall: Option<String>,
+    block: Option<String>,
+    space_from: Option<i32>,
+    space_to: Option<i32>,
+}
 
-    let mut conn = pool.get().expect("Failed to get database connection");
+fn circles_query(filter: &CirclesFilter) -> CirclesQuery {
+    use crate::schema::circle_events;
 
     let mut query = circles::table
         .left_join(circle_artists::table.on(circles::id.eq(circle_artists::circle_id)))
         .left_join(artists::table.on(circle_artists::artist_id.eq(artists::id)))
         .into_boxed();
 
-    if let Some(name) = name {
-        query = query.filter(circles::name.similar_to(format!("%{}%", name)));
+    if let Some(name) = &filter.name {
+        query = query.filter(name_matches(circles::name, name));
+    }
+
+    if let Some(artist_name) = &filter.artist_name {
+        query = query.filter(name_matches(artists::name.nullable(), artist_name));
+    }
+
+    if let Some(booth) = &filter.booth {
+        query = query
+            .filter(circles::booth_block.eq(booth.block.clone()))
+            .filter(circles::booth_space.eq(booth.space));
+
+        if let Some(booth_hall) = &booth.hall {
+            query = query.filter(circles::booth_hall.eq(booth_hall.clone()));
+        }
+
+        if let Some(booth_half) = &booth.half {
+            query = query.filter(circles::booth_half.eq(booth_half.clone()));
+        }
+    }
+
+    if let Some(hall) = &filter.hall {
+        query = query.filter(circles::booth_hall.eq(hall.to_uppercase()));
+    }
+
+    if let Some(block) = &filter.block {
+        query = query.filter(circles::booth_block.eq(block.to_uppercase()));
+    }
+
+    if let Some(space_from) = filter.space_from {
+        query = query.filter(circles::booth_space.ge(space_from));
     }
 
-    if let Some(artist_name) = artist_name {
-        query = query.filter(artists::name.similar_to(format!("%{}%", artist_name)));
+    if let Some(space_to) = filter.space_to {
+        query = query.filter(circles::booth_space.le(space_to));
     }
 
-    if let Some(location) = location {
-        query = query.filter(circles::location.like(format!("%{}%", location)));
+    if let Some(event_id) = filter.event_id {
+        query = query.filter(
+            circles::id.eq_any(
+                circle_events::table
+                    .filter(circle_events::event_id.eq(event_id))
+                    .select(circle_events::circle_id),
+            ),
+        );
     }
 
     query
+}
+
+#[allow(clippy::too_many_arguments)]
+#[get("/circles?<name>&<artist_name>&<location>&<event_id>&<hall>&<block>&<space_from>&<space_to>&<limit>&<cursor>&<sort>")]
+pub fn get_circles(
+    name: Option<String>,
+    artist_name: Option<String>,
+    location: Option<String>,
+    event_id: Option<i32>,
+    hall: Option<String>,
+    block: Option<String>,
+    space_from: Option<i32>,
+    space_to: Option<i32>,
+    limit: Option<i64>,
+    cursor: Option<String>,
+    sort: Option<String>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Page<Circle>>, CustomError> {
+    let sort = Sort::new(
+        sort.or(Some("booth".into())),
+        "circles.id",
+        &[
+            ("name", &["circles.name"]),
+            (
+                "booth",
+                &[
+                    "circles.booth_hall",
+                    "circles.booth_block",
+                    "circles.booth_space",
+                    "circles.booth_half",
+                ],
+            ),
+        ],
+    )?;
+    let page_request = PageRequest::new(limit, cursor.as_deref(), &sort)?;
+
+    let filter = CirclesFilter {
+        name,
+        artist_name,
+        booth: location.as_deref().map(parse_booth).transpose()?,
+        event_id,
+        hall,
+        block,
+        space_from,
+        space_to,
+    };
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let total = circles_query(&filter)
+        .select(count_distinct(circles::id))
+        .first::<i64>(&mut conn)
+        .map_err(handle_error)?;
+
+    let mut query = circles_query(&filter);
+
+    query = match (sort.key.as_str(), sort.descending) {
+        ("name", false) => query.order_by((circles::name.asc(), circles::id.asc())),
+        ("name", true) => query.order_by((circles::name.desc(), circles::id.desc())),
+        ("booth", false) => query.order_by((
+            circles::booth_hall.asc(),
+            circles::booth_block.asc(),
+            circles::booth_space.asc(),
+            circles::booth_half.asc(),
+            circles::id.asc(),
+        )),
+        ("booth", true) => query.order_by((
+            circles::booth_hall.desc(),
+            circles::booth_block.desc(),
+            circles::booth_space.desc(),
+            circles::booth_half.desc(),
+            circles::id.desc(),
+        )),
+        (_, false) => query.order_by(circles::id.asc()),
+        (_, true) => query.order_by(circles::id.desc()),
+    };
+
+    if let Some(after) = page_request.after() {
+        query = query.filter(after);
+    }
+
+    let circles = query
         .select(circles::all_columns)
         .distinct()
+        .limit(page_request.fetch_limit())
         .load::<Circle>(&mut conn)
-        .map(Json)
-        .map_err(handle_error)
+        .map_err(handle_error)?;
+
+    Ok(Json(page_request.page(circles, total, |circle| {
+        let key = match sort.key.as_str() {
+            "name" => vec![circle.name.clone().map(SortValue::from)],
+            "booth" => vec![
+                circle.booth_hall.clone().map(SortValue::from),
+                circle.booth_block.clone().map(SortValue::from),
+                circle.booth_space.map(SortValue::from),
+                circle.booth_half.clone().map(SortValue::from),
+            ],
+            _ => vec![],
+        };
+        (key, circle.id)
+    })))
 }
 
 #[get("/circles/<circle_id>")]
@@ -152,31 +314,77 @@ pub struct NewCircle {
     pub location: Option<String>,
 }
 
-#[derive(Queryable, Selectable, Insertable, Deserialize, AsChangeset)]
+#[derive(Deserialize, AsChangeset)]
 #[diesel(table_name = crate::schema::circles)]
 pub struct UpdateCircle {
     pub name: Option<String>,
     pub description: Option<String>,
-    pub location: Option<String>,
+    /// Left out to keep the location, `null` or empty to clear it.
+    #[serde(default, deserialize_with = "present")]
+    pub location: Option<Option<String>>,
+}
+
+/// Tells a field given as `null` (`Some(None)`) from one left out (`None`).
+fn present<'de, D>(deserializer: D) -> Result<Option<Option<String>>, D::Error>
+where
+    D: rocket::serde::Deserializer<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::circles)]
+#[diesel(treat_none_as_null = true)]
+pub struct CircleBooth {
+    pub booth_hall: Option<String>,
+    pub booth_block: Option<String>,
+    pub booth_space: Option<i32>,
+    pub booth_half: Option<String>,
+}
+
+impl From<Option<Booth>> for CircleBooth {
+    fn from(booth: Option<Booth>) -> Self {
+        match booth {
+            Some(booth) => CircleBooth {
+                booth_hall: booth.hall,
+                booth_block: Some(booth.block),
+                booth_space: Some(booth.space),
+                booth_half: booth.half,
+            },
+            None => CircleBooth {
+                booth_hall: None,
+                booth_block: None,
+                booth_space: None,
+                booth_half: None,
+            },
+        }
+    }
 }
 
 #[post("/circles", format = "json", data = "<new_circle>")]
 pub fn post_circle(
     user: AuthenticatedUser,
     new_circle: Json<NewCircle>,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<Created<Json<Circle>>, CustomError> {
     use crate::schema::circles;
 
     user.check_moderator()?;
 
+    let mut new_circle = new_circle.into_inner();
+    let booth = new_circle.location.as_deref().map(parse_booth).transpose()?;
+    new_circle.location = booth.as_ref().map(Booth::to_string);
+
     let mut conn = pool.get().expect("Failed to get database connection");
 
     let circle = diesel::insert_into(circles::dsl::circles)
-        .values(new_circle.into_inner())
+        .values((new_circle, CircleBooth::from(booth)))
         .get_result::<Circle>(&mut conn)
         .map_err(handle_error)?;
 
+    feed.publish(ChangeKind::circle, ChangeAction::created, circle.id, circle.id);
+
     Ok(Created::new(format!("/circles/{}", circle.id)).body(Json(circle)))
 }
 
@@ -185,30 +393,49 @@ pub fn patch_circle(
     user: AuthenticatedUser,
     circle_id: i32,
     update_circle: Json<UpdateCircle>,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<Json<Circle>, CustomError> {
     use crate::schema::circles::dsl::*;
 
     user.check_permission(circle_id)?;
 
+    let mut update_circle = update_circle.into_inner();
+
+    // The booth columns follow the location in the same update, and are
+    // cleared along with it.
+    let booth = match update_circle.location.take() {
+        Some(Some(given)) if !given.trim().is_empty() => {
+            let booth = parse_booth(&given)?;
+            update_circle.location = Some(Some(booth.to_string()));
+
+            Some(CircleBooth::from(Some(booth)))
+        }
+        Some(_) => {
+            update_circle.location = Some(None);
+
+            Some(CircleBooth::from(None))
+        }
+        None => None,
+    };
+
     let mut conn = pool.get().expect("Failed to get database connection");
 
-    diesel::update(circles.find(circle_id))
-        .set(update_circle.into_inner())
-        .execute(&mut conn)
+    let circle = diesel::update(circles.find(circle_id))
+        .set((update_circle, booth))
+        .get_result::<Circle>(&mut conn)
         .map_err(handle_error)?;
 
-    circles
-        .find(circle_id)
-        .first(&mut conn)
-        .map(Json)
-        .map_err(handle_error)
+    feed.publish(ChangeKind::circle, ChangeAction::updated, circle_id, circle_id);
+
+    Ok(Json(circle))
 }
 
 #[delete("/circles/<circle_id>")]
 pub fn delete_circle(
     user: AuthenticatedUser,
     circle_id: i32,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<(), CustomError> {
     use crate::schema::circle_artists;
@@ -255,6 +482,8 @@ pub fn delete_circle(
             Json(ErrorInfo::new("not_found".to_string())),
         ))
     } else {
+        feed.publish(ChangeKind::circle, ChangeAction::deleted, circle_id, circle_id);
+
         Ok(())
     }
 }
@@ -0,0 +1,421 @@
+use crate::error_handler::{handle_error, CustomError, ErrorInfo};
+use crate::models::{AuthenticatedUser, Collection, FullGood, Good};
+use crate::routes::goods::load_full_goods_with;
+use crate::utils::strings::generate_random_string;
+use crate::DbPool;
+
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use rocket::http::Status;
+use rocket::response::status::{Created, Custom};
+use rocket::serde::json::Json;
+use rocket::serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Deserialize)]
+pub struct NewCollection {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::collections)]
+pub struct InsertCollection {
+    pub user_id: i32,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize, AsChangeset)]
+#[diesel(table_name = crate::schema::collections)]
+pub struct UpdateCollection {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct NewCollectionGood {
+    pub goods_id: i32,
+    pub position: Option<i32>,
+    pub note: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::collection_goods)]
+pub struct InsertCollectionGood {
+    pub collection_id: i32,
+    pub goods_id: i32,
+    pub position: i32,
+    pub note: Option<String>,
+}
+
+#[derive(Deserialize, AsChangeset)]
+#[diesel(table_name = crate::schema::collection_goods)]
+pub struct UpdateCollectionGood {
+    pub position: Option<i32>,
+    pub note: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CollectionItem {
+    pub position: i32,
+    pub note: Option<String>,
+    pub goods: FullGood,
+}
+
+#[derive(Serialize)]
+pub struct FullCollection {
+    pub id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub share_token: Option<String>,
+    pub items: Vec<CollectionItem>,
+}
+
+#[derive(Serialize)]
+pub struct SharedCollection {
+    pub name: String,
+    pub description: Option<String>,
+    pub owner: String,
+    pub items: Vec<CollectionItem>,
+}
+
+fn find_own_collection(
+    user: &AuthenticatedUser,
+    collection_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Collection, CustomError> {
+    use crate::schema::collections;
+
+    collections::table
+        .find(collection_id)
+        .filter(collections::user_id.eq(user.id))
+        .first::<Collection>(conn)
+        .map_err(handle_error)
+}
+
+fn load_collection_items(
+    collection_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<CollectionItem>, CustomError> {
+    use crate::schema::collection_goods;
+    use crate::schema::goods;
+
+    let rows = collection_goods::table
+        .inner_join(goods::table)
+        .filter(collection_goods::collection_id.eq(collection_id))
+        .order((collection_goods::position, collection_goods::id))
+        .select((
+            collection_goods::position,
+            collection_goods::note,
+            goods::all_columns,
+        ))
+        .load::<(i32, Option<String>, Good)>(conn)
+        .map_err(handle_error)?;
+
+    let rows = rows
+        .into_iter()
+        .map(|(position, note, good)| ((position, note), good))
+        .collect();
+
+    Ok(load_full_goods_with(rows, conn)?
+        .into_iter()
+        .map(|((position, note), goods)| CollectionItem {
+            position,
+            note,
+            goods,
+        })
+        .collect())
+}
+
+#[post("/collections", format = "json", data = "<new_collection>")]
+pub fn post_collection(
+    user: AuthenticatedUser,
+    new_collection: Json<NewCollection>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<Json<Collection>>, CustomError> {
+    use crate::schema::collections;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let new_collection = new_collection.into_inner();
+
+    let collection = diesel::insert_into(collections::table)
+        .values(InsertCollection {
+            user_id: user.id,
+            name: new_collection.name,
+            description: new_collection.description,
+        })
+        .get_result::<Collection>(&mut conn)
+        .map_err(handle_error)?;
+
+    Ok(Created::new(format!("/collections/{}", collection.id)).body(Json(collection)))
+}
+
+#[get("/users/me/collections")]
+pub fn get_my_collections(
+    user: AuthenticatedUser,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Vec<Collection>>, CustomError> {
+    use crate::schema::collections;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    collections::table
+        .filter(collections::user_id.eq(user.id))
+        .order(collections::id)
+        .load::<Collection>(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
+
+#[get("/collections/<collection_id>")]
+pub fn get_collection_by_id(
+    user: AuthenticatedUser,
+    collection_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<FullCollection>, CustomError> {
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let collection = find_own_collection(&user, collection_id, &mut conn)?;
+    let items = load_collection_items(collection.id, &mut conn)?;
+
+    Ok(Json(FullCollection {
+        id: collection.id,
+        name: collection.name,
+        description: collection.description,
+        share_token: collection.share_token,
+        items,
+    }))
+}
+
+#[patch(
+    "/collections/<collection_id>",
+    format = "json",
+    data = "<update_collection>"
+)]
+pub fn patch_collection(
+    user: AuthenticatedUser,
+    collection_id: i32,
+    update_collection: Json<UpdateCollection>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Collection>, CustomError> {
+    use crate::schema::collections;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_own_collection(&user, collection_id, &mut conn)?;
+
+    diesel::update(collections::table.find(collection_id))
+        .set(update_collection.into_inner())
+        .get_result::<Collection>(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
+
+#[delete("/collections/<collection_id>")]
+pub fn delete_collection(
+    user: AuthenticatedUser,
+    collection_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::collections;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let size = diesel::delete(
+        collections::table
+            .find(collection_id)
+            .filter(collections::user_id.eq(user.id)),
+    )
+    .execute(&mut conn)
+    .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[post(
+    "/collections/<collection_id>/goods",
+    format = "json",
+    data = "<new_goods>"
+)]
+pub fn post_collection_goods(
+    user: AuthenticatedUser,
+    collection_id: i32,
+    new_goods: Json<NewCollectionGood>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<()>, CustomError> {
+    use crate::schema::collection_goods;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_own_collection(&user, collection_id, &mut conn)?;
+
+    let new_goods = new_goods.into_inner();
+
+    let position = match new_goods.position {
+        Some(position) => position,
+        None => collection_goods::table
+            .filter(collection_goods::collection_id.eq(collection_id))
+            .select(diesel::dsl::max(collection_goods::position))
+            .first::<Option<i32>>(&mut conn)
+            .map_err(handle_error)?
+            .map_or(0, |position| position + 1),
+    };
+
+    diesel::insert_into(collection_goods::table)
+        .values(InsertCollectionGood {
+            collection_id,
+            goods_id: new_goods.goods_id,
+            position,
+            note: new_goods.note,
+        })
+        .execute(&mut conn)
+        .map_err(handle_error)?;
+
+    Ok(Created::new(format!(
+        "/collections/{}/goods/{}",
+        collection_id, new_goods.goods_id
+    )))
+}
+
+#[patch(
+    "/collections/<collection_id>/goods/<goods_id>",
+    format = "json",
+    data = "<update_goods>"
+)]
+pub fn patch_collection_goods(
+    user: AuthenticatedUser,
+    collection_id: i32,
+    goods_id: i32,
+    update_goods: Json<UpdateCollectionGood>,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::collection_goods;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_own_collection(&user, collection_id, &mut conn)?;
+
+    let size = diesel::update(
+        collection_goods::table
+            .filter(collection_goods::collection_id.eq(collection_id))
+            .filter(collection_goods::goods_id.eq(goods_id)),
+    )
+    .set(update_goods.into_inner())
+    .execute(&mut conn)
+    .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[delete("/collections/<collection_id>/goods/<goods_id>")]
+pub fn delete_collection_goods(
+    user: AuthenticatedUser,
+    collection_id: i32,
+    goods_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::collection_goods;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_own_collection(&user, collection_id, &mut conn)?;
+
+    let size = diesel::delete(
+        collection_goods::table
+            .filter(collection_goods::collection_id.eq(collection_id))
+            .filter(collection_goods::goods_id.eq(goods_id)),
+    )
+    .execute(&mut conn)
+    .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Issues a new share token, invalidating any link handed out before.
+#[post("/collections/<collection_id>/share")]
+pub fn post_collection_share(
+    user: AuthenticatedUser,
+    collection_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Collection>, CustomError> {
+    use crate::schema::collections;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_own_collection(&user, collection_id, &mut conn)?;
+
+    diesel::update(collections::table.find(collection_id))
+        .set(collections::share_token.eq(generate_random_string(24)))
+        .get_result::<Collection>(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
+
+#[delete("/collections/<collection_id>/share")]
+pub fn delete_collection_share(
+    user: AuthenticatedUser,
+    collection_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Collection>, CustomError> {
+    use crate::schema::collections;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_own_collection(&user, collection_id, &mut conn)?;
+
+    diesel::update(collections::table.find(collection_id))
+        .set(collections::share_token.eq(None::<String>))
+        .get_result::<Collection>(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
+
+#[get("/shared/collections/<share_token>")]
+pub fn get_shared_collection(
+    share_token: String,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<SharedCollection>, CustomError> {
+    use crate::schema::collections;
+    use crate::schema::users;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let (collection, owner) = collections::table
+        .inner_join(users::table)
+        .filter(collections::share_token.eq(share_token))
+        .select((collections::all_columns, users::nickname))
+        .first::<(Collection, String)>(&mut conn)
+        .map_err(handle_error)?;
+
+    let items = load_collection_items(collection.id, &mut conn)?;
+
+    Ok(Json(SharedCollection {
+        name: collection.name,
+        description: collection.description,
+        owner,
+        items,
+    }))
+}
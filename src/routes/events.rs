@@ -0,0 +1,303 @@
+use std::time::SystemTime;
+
+use crate::error_handler::{handle_error, CustomError, ErrorInfo};
+use crate::models::{AuthenticatedUser, CircleEvent, Event};
+use crate::routes::circles::parse_booth;
+use crate::DbPool;
+
+use diesel::prelude::*;
+use rocket::http::Status;
+use rocket::response::status::{Created, Custom};
+use rocket::serde::json::Json;
+use rocket::serde::Deserialize;
+
+#[derive(Queryable, Selectable, Insertable, Deserialize, AsChangeset)]
+#[diesel(table_name = crate::schema::events)]
+pub struct NewEvent {
+    pub name: String,
+    pub venue: Option<String>,
+    pub start_date: SystemTime,
+    pub end_date: SystemTime,
+}
+
+#[derive(Queryable, Selectable, Insertable, Deserialize, AsChangeset)]
+#[diesel(table_name = crate::schema::events)]
+pub struct UpdateEvent {
+    pub name: Option<String>,
+    pub venue: Option<String>,
+    pub start_date: Option<SystemTime>,
+    pub end_date: Option<SystemTime>,
+}
+
+#[post("/events", format = "json", data = "<new_event>")]
+pub fn post_event(
+    user: AuthenticatedUser,
+    new_event: Json<NewEvent>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<Json<Event>>, CustomError> {
+    use crate::schema::events;
+
+    user.check_moderator()?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let event = diesel::insert_into(events::dsl::events)
+        .values(new_event.into_inner())
+        .get_result::<Event>(&mut conn)
+        .map_err(handle_error)?;
+
+    Ok(Created::new(format!("/events/{}", event.id)).body(Json(event)))
+}
+
+#[get("/events?<name>&<circle_id>")]
+pub fn get_events(
+    name: Option<String>,
+    circle_id: Option<i32>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Vec<Event>>, CustomError> {
+    use crate::schema::circle_events;
+    use crate::schema::events;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let mut query = events::table.into_boxed();
+
+    if let Some(name) = name {
+        query = query.filter(events::name.similar_to(format!("%{}%", name)));
+    }
+
+    if let Some(circle_id) = circle_id {
+        query = query.filter(
+            events::id.eq_any(
+                circle_events::table
+                    .filter(circle_events::circle_id.eq(circle_id))
+                    .select(circle_events::event_id),
+            ),
+        );
+    }
+
+    query
+        .order(events::start_date.desc())
+        .load::<Event>(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
+
+#[get("/events/<event_id>")]
+pub fn get_event_by_id(
+    event_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Event>, CustomError> {
+    use crate::schema::events::dsl::events;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    events
+        .find(event_id)
+        .first(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
+
+#[patch("/events/<event_id>", format = "json", data = "<update_event>")]
+pub fn patch_event(
+    user: AuthenticatedUser,
+    event_id: i32,
+    update_event: Json<UpdateEvent>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Event>, CustomError> {
+    use crate::schema::events::dsl::*;
+
+    user.check_moderator()?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    diesel::update(events.find(event_id))
+        .set(update_event.into_inner())
+        .execute(&mut conn)
+        .map_err(handle_error)?;
+
+    events
+        .find(event_id)
+        .first(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
+
+#[delete("/events/<event_id>")]
+pub fn delete_event(
+    user: AuthenticatedUser,
+    event_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::events::dsl::*;
+
+    user.check_moderator()?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let size = diesel::delete(events.find(event_id))
+        .execute(&mut conn)
+        .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[get("/events/<event_id>/circles")]
+pub fn get_event_circles(
+    event_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Vec<CircleEvent>>, CustomError> {
+    use crate::schema::circle_events;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    circle_events::table
+        .filter(circle_events::event_id.eq(event_id))
+        .order(circle_events::location.asc())
+        .load::<CircleEvent>(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
+
+#[derive(Deserialize)]
+pub struct NewParticipation {
+    pub event_id: i32,
+    pub location: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::circle_events)]
+pub struct NewCircleEvent {
+    pub circle_id: i32,
+    pub event_id: i32,
+    pub location: Option<String>,
+}
+
+#[derive(Deserialize, AsChangeset)]
+#[diesel(table_name = crate::schema::circle_events)]
+pub struct UpdateCircleEvent {
+    pub location: Option<String>,
+}
+
+#[post(
+    "/circles/<circle_id>/events",
+    format = "json",
+    data = "<new_participation>"
+)]
+pub fn post_circle_event(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    new_participation: Json<NewParticipation>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<Json<CircleEvent>>, CustomError> {
+    use crate::schema::circle_events;
+
+    user.check_permission(circle_id)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let new_participation = new_participation.into_inner();
+    let location = new_participation
+        .location
+        .as_deref()
+        .map(parse_booth)
+        .transpose()?
+        .map(|booth| booth.to_string());
+
+    let circle_event = diesel::insert_into(circle_events::dsl::circle_events)
+        .values(NewCircleEvent {
+            circle_id,
+            event_id: new_participation.event_id,
+            location,
+        })
+        .get_result::<CircleEvent>(&mut conn)
+        .map_err(handle_error)?;
+
+    Ok(Created::new(format!(
+        "/circles/{}/events/{}",
+        circle_id, circle_event.event_id
+    ))
+    .body(Json(circle_event)))
+}
+
+#[patch(
+    "/circles/<circle_id>/events/<event_id>",
+    format = "json",
+    data = "<update_circle_event>"
+)]
+pub fn patch_circle_event(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    event_id: i32,
+    update_circle_event: Json<UpdateCircleEvent>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<CircleEvent>, CustomError> {
+    use crate::schema::circle_events;
+
+    user.check_permission(circle_id)?;
+
+    let mut update_circle_event = update_circle_event.into_inner();
+    update_circle_event.location = update_circle_event
+        .location
+        .as_deref()
+        .map(parse_booth)
+        .transpose()?
+        .map(|booth| booth.to_string());
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    diesel::update(
+        circle_events::dsl::circle_events
+            .filter(circle_events::dsl::circle_id.eq(circle_id))
+            .filter(circle_events::dsl::event_id.eq(event_id)),
+    )
+    .set(update_circle_event)
+    .execute(&mut conn)
+    .map_err(handle_error)?;
+
+    circle_events::dsl::circle_events
+        .filter(circle_events::dsl::circle_id.eq(circle_id))
+        .filter(circle_events::dsl::event_id.eq(event_id))
+        .first(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
+
+#[delete("/circles/<circle_id>/events/<event_id>")]
+pub fn delete_circle_event(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    event_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::circle_events;
+
+    user.check_permission(circle_id)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let size = diesel::delete(
+        circle_events::dsl::circle_events
+            .filter(circle_events::dsl::circle_id.eq(circle_id))
+            .filter(circle_events::dsl::event_id.eq(event_id)),
+    )
+    .execute(&mut conn)
+    .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
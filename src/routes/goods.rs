@@ -1,9 +1,22 @@
+use std::collections::HashMap;
+
 use crate::error_handler::{handle_error, CustomError, ErrorInfo};
-use crate::models::{AuthenticatedUser, Good, CharacterWithReference, FullGood, Category};
+use crate::models::{
+    AuthenticatedUser, AvailabilityTypeEnum, Category, CharacterWithReference, FullGood, Good,
+};
+use crate::routes::characters::{characters_named, load_aliases};
+use crate::routes::stream::{ChangeAction, ChangeFeed, ChangeKind};
+use crate::schema::sql_types::AvailabilityType;
+use crate::schema::{characters, circle_goods, goods, goods_character, goods_in_bundle};
+use crate::utils::pagination::{Page, PageRequest, Sort, SortValue};
+use crate::utils::search::name_matches;
 use crate::DbPool;
 
-use diesel::dsl::sql;
+use diesel::dsl::{count_distinct, sql, IntoBoxed, LeftJoinOn};
+use diesel::expression::SqlLiteral;
+use diesel::pg::Pg;
 use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
 use diesel::sql_types::{Text, Integer};
 use rocket::http::Status;
 use rocket::response::status::{Created, Custom};
@@ -30,6 +43,100 @@ pub struct UpdateGood {
     pub image_name: Option<String>,
 }
 
+/// Body of the stock endpoints for goods and bundles.
+#[derive(Deserialize)]
+pub struct UpdateStock {
+    pub stock: Option<i32>,
+    pub availability: Option<AvailabilityTypeEnum>,
+}
+
+impl UpdateStock {
+    /// Validates the update. Unless an availability is given explicitly, it
+    /// follows the new stock as described in [`StockChange::availability`].
+    pub(crate) fn resolve(
+        self,
+    ) -> Result<(Option<i32>, Option<SqlLiteral<AvailabilityType>>), CustomError> {
+        match self.stock {
+            None if self.availability.is_none() => Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new("Nothing to update".into())),
+            )),
+            Some(stock) if stock < 0 => Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new("Stock cannot be negative".into())),
+            )),
+            stock => {
+                let availability = match (self.availability, stock) {
+                    (Some(availability), _) => {
+                        Some(sql(&format!("'{}'", availability_name(availability))))
+                    }
+                    (None, Some(stock)) => Some(StockChange::To(stock).availability()),
+                    (None, None) => None,
+                };
+
+                Ok((stock, availability))
+            }
+        }
+    }
+}
+
+/// A change to the stock of goods or bundles.
+pub(crate) enum StockChange {
+    To(i32),
+    By(i32),
+}
+
+impl StockChange {
+    /// The availability to set in the same `UPDATE` as the stock: sold out
+    /// when none is left, and back on sale when stock is added to a sold out
+    /// item. `low` is only ever set explicitly, and untracked stock leaves
+    /// the availability as it is.
+    pub(crate) fn availability(&self) -> SqlLiteral<AvailabilityType> {
+        let (stock, adds) = match *self {
+            StockChange::To(stock) => (stock.to_string(), stock > 0),
+            StockChange::By(delta) => (format!("stock + {}", delta), delta > 0),
+        };
+
+        let restock = if adds {
+            format!(
+                "WHEN {} > 0 AND availability = 'sold_out' THEN 'available' ",
+                stock
+            )
+        } else {
+            String::new()
+        };
+
+        sql(&format!(
+            "CASE WHEN {} = 0 THEN 'sold_out' {}ELSE availability END",
+            stock, restock
+        ))
+    }
+}
+
+fn availability_name(availability: AvailabilityTypeEnum) -> &'static str {
+    match availability {
+        AvailabilityTypeEnum::available => "available",
+        AvailabilityTypeEnum::low => "low",
+        AvailabilityTypeEnum::sold_out => "sold_out",
+        AvailabilityTypeEnum::not_on_sale => "not_on_sale",
+    }
+}
+
+pub(crate) fn parse_availability(
+    availability: &str,
+) -> Result<AvailabilityTypeEnum, CustomError> {
+    match availability {
+        "available" => Ok(AvailabilityTypeEnum::available),
+        "low" => Ok(AvailabilityTypeEnum::low),
+        "sold_out" => Ok(AvailabilityTypeEnum::sold_out),
+        "not_on_sale" => Ok(AvailabilityTypeEnum::not_on_sale),
+        _ => Err(Custom(
+            Status::BadRequest,
+            Json(ErrorInfo::new(format!("Unknown availability {}", availability))),
+        )),
+    }
+}
+
 #[derive(Insertable)]
 #[diesel(table_name = crate::schema::circle_goods)]
 pub struct NewCircleGoods {
@@ -42,6 +149,7 @@ pub fn post_circle_goods(
     user: AuthenticatedUser,
     circle_id: i32,
     new_goods: Json<NewGood>,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<Created<Json<Good>>, CustomError> {
     use crate::schema::circle_goods;
@@ -70,26 +178,73 @@ pub fn post_circle_goods(
             Json(ErrorInfo::new("not_found".to_string())),
         ))
     } else {
+        feed.publish(ChangeKind::goods, ChangeAction::created, good.id, circle_id);
+
         Ok(Created::new(format!("/goods/{}", good.id)).body(Json(good)))
     }
 }
 
-#[get("/goods?<name>&<character_id>&<ref_id>&<bundle_id>&<circle_id>")]
-pub fn get_goods(
+type GoodsQuery = IntoBoxed<
+    'static,
+    LeftJoinOn<
+        LeftJoinOn<
+            LeftJoinOn<
+                LeftJoinOn<
+                    goods::table,
+                    goods_character::table,
+                    diesel::dsl::Eq<goods::id, goods_character::goods_id>,
+                >,
+                characters::table,
+                diesel::dsl::Eq<goods_character::character_id, characters::id>,
+            >,
+            goods_in_bundle::table,
+            diesel::dsl::Eq<goods::id, goods_in_bundle::goods_id>,
+        >,
+        circle_goods::table,
+        diesel::dsl::Eq<goods::id, circle_goods::goods_id>,
+    >,
+    Pg,
+>;
+
+/// How multiple `character_id`s combine: goods featuring any of them, or
+/// only goods featuring all of them together.
+#[derive(PartialEq)]
+enum CharacterMatch {
+    Any,
+    All,
+}
+
+impl CharacterMatch {
+    fn new(character_match: Option<&str>) -> Result<Self, CustomError> {
+        match character_match {
+            None | Some("any") => Ok(CharacterMatch::Any),
+            Some("all") => Ok(CharacterMatch::All),
+            Some(_) => Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new("character_match must be any or all".into())),
+            )),
+        }
+    }
+}
+
+struct GoodsFilter {
     name: Option<String>,
-    character_id: Option<i32>,
+    character_ids: Vec<i32>,
+    character_match: CharacterMatch,
+    character_name: Option<String>,
     ref_id: Option<i32>,
     bundle_id: Option<i32>,
     circle_id: Option<i32>,
-    pool: &rocket::State<DbPool>,
-) -> Result<Json<Vec<FullGood>>, CustomError> {
-    use crate::schema::characters;
-    use crate::schema::circle_goods;
-    use crate::schema::goods;
-    use crate::schema::goods_character;
-    use crate::schema::goods_in_bundle;
+    event_id: Option<i32>,
+    category_ids: Vec<i32>,
+    min_price: Option<i32>,
+    max_price: Option<i32>,
+    has_image: Option<bool>,
+    availability: Vec<AvailabilityTypeEnum>,
+}
 
-    let mut conn = pool.get().expect("Failed to get database connection");
+fn goods_query(filter: &GoodsFilter) -> GoodsQuery {
+    use crate::schema::circle_events;
 
     // Start with the base query
     let mut query = goods::dsl::goods
@@ -100,81 +255,331 @@ pub fn get_goods(
         .into_boxed();
 
     // Apply filters based on query parameters
-    if let Some(name_filter) = name {
-        query = query.filter(goods::name.similar_to(format!("%{}%", name_filter)));
+    if let Some(name_filter) = &filter.name {
+        query = query.filter(name_matches(goods::name, name_filter));
     }
 
-    if let Some(character_id_filter) = character_id {
+    if !filter.character_ids.is_empty() {
         // Apply character_id filter
-        query = query.filter(goods_character::dsl::character_id.eq(character_id_filter));
+        if filter.character_match == CharacterMatch::All {
+            // goods_character is already joined, so match against an alias
+            let matched = diesel::alias!(goods_character as matched_characters);
+
+            query = query.filter(
+                goods::id.eq_any(
+                    matched
+                        .filter(
+                            matched
+                                .field(goods_character::character_id)
+                                .eq_any(filter.character_ids.clone()),
+                        )
+                        .group_by(matched.field(goods_character::goods_id))
+                        .having(
+                            count_distinct(matched.field(goods_character::character_id))
+                                .eq(filter.character_ids.len() as i64),
+                        )
+                        .select(matched.field(goods_character::goods_id)),
+                ),
+            );
+        } else {
+            query = query.filter(
+                goods_character::dsl::character_id.eq_any(filter.character_ids.clone()),
+            );
+        }
+    }
+
+    if let Some(character_name) = &filter.character_name {
+        query = query.filter(characters::id.eq_any(characters_named(character_name)));
     }
 
-    if let Some(ref_id_filter) = ref_id {
+    if let Some(ref_id_filter) = filter.ref_id {
         // Apply ref_id filter
         query = query.filter(characters::dsl::reference_id.eq(ref_id_filter));
     }
 
-    if let Some(bundle_id_filter) = bundle_id {
+    if let Some(bundle_id_filter) = filter.bundle_id {
         // Apply bundle_id filter
         query = query.filter(goods_in_bundle::dsl::bundle_id.eq(bundle_id_filter));
     }
 
-    if let Some(circle_id_filter) = circle_id {
+    if let Some(circle_id_filter) = filter.circle_id {
         // Apply circle_id filter
         query = query.filter(circle_goods::dsl::circle_id.eq(circle_id_filter));
     }
 
+    if let Some(event_id_filter) = filter.event_id {
+        // Apply event_id filter
+        query = query.filter(
+            circle_goods::dsl::circle_id.eq_any(
+                circle_events::table
+                    .filter(circle_events::event_id.eq(event_id_filter))
+                    .select(circle_events::circle_id),
+            ),
+        );
+    }
+
+    if !filter.category_ids.is_empty() {
+        query = query.filter(goods::category_id.eq_any(filter.category_ids.clone()));
+    }
+
+    if let Some(min_price) = filter.min_price {
+        query = query.filter(goods::price.ge(min_price));
+    }
+
+    if let Some(max_price) = filter.max_price {
+        query = query.filter(goods::price.le(max_price));
+    }
+
+    match filter.has_image {
+        Some(true) => query = query.filter(goods::image_name.is_not_null()),
+        Some(false) => query = query.filter(goods::image_name.is_null()),
+        None => {}
+    }
+
+    if !filter.availability.is_empty() {
+        query = query.filter(goods::availability.eq_any(filter.availability.clone()));
+    }
+
+    query
+}
+
+#[allow(clippy::too_many_arguments)]
+#[get("/goods?<name>&<character_id>&<character_match>&<character_name>&<ref_id>&<bundle_id>&<circle_id>&<event_id>&<category_id>&<min_price>&<max_price>&<has_image>&<availability>&<limit>&<cursor>&<sort>")]
+pub fn get_goods(
+    name: Option<String>,
+    character_id: Vec<i32>,
+    character_match: Option<String>,
+    character_name: Option<String>,
+    ref_id: Option<i32>,
+    bundle_id: Option<i32>,
+    circle_id: Option<i32>,
+    event_id: Option<i32>,
+    category_id: Vec<i32>,
+    min_price: Option<i32>,
+    max_price: Option<i32>,
+    has_image: Option<bool>,
+    availability: Vec<String>,
+    limit: Option<i64>,
+    cursor: Option<String>,
+    sort: Option<String>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Page<FullGood>>, CustomError> {
+    let sort = Sort::new(
+        sort,
+        "goods.id",
+        &[("name", &["goods.name"]), ("price", &["goods.price"])],
+    )?;
+    let page_request = PageRequest::new(limit, cursor.as_deref(), &sort)?;
+
+    if let (Some(min_price), Some(max_price)) = (min_price, max_price) {
+        if min_price > max_price {
+            return Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new("min_price cannot exceed max_price".into())),
+            ));
+        }
+    }
+
+    // `character_match=all` counts distinct characters per goods, so a
+    // repeated id must not count twice.
+    let mut character_ids = character_id;
+    character_ids.sort_unstable();
+    character_ids.dedup();
+
+    let filter = GoodsFilter {
+        name,
+        character_ids,
+        character_match: CharacterMatch::new(character_match.as_deref())?,
+        character_name,
+        ref_id,
+        bundle_id,
+        circle_id,
+        event_id,
+        category_ids: category_id,
+        min_price,
+        max_price,
+        has_image,
+        availability: availability
+            .iter()
+            .map(|availability| parse_availability(availability))
+            .collect::<Result<_, _>>()?,
+    };
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let total = goods_query(&filter)
+        .select(count_distinct(goods::id))
+        .first::<i64>(&mut conn)
+        .map_err(handle_error)?;
+
+    let mut query = goods_query(&filter);
+
+    query = match (sort.key.as_str(), sort.descending) {
+        ("name", false) => query.order_by((goods::name.asc(), goods::id.asc())),
+        ("name", true) => query.order_by((goods::name.desc(), goods::id.desc())),
+        ("price", false) => query.order_by((goods::price.asc(), goods::id.asc())),
+        ("price", true) => query.order_by((goods::price.desc(), goods::id.desc())),
+        (_, false) => query.order_by(goods::id.asc()),
+        (_, true) => query.order_by(goods::id.desc()),
+    };
+
+    if let Some(after) = page_request.after() {
+        query = query.filter(after);
+    }
+
     // Execute the final query and return the result
     let goods = query
         .select(goods::all_columns)
         .distinct()
+        .limit(page_request.fetch_limit())
         .load::<Good>(&mut conn)
         .map_err(handle_error)?;
 
-    Ok(Json(goods.iter().map(|good: &Good| {
-        use crate::schema::categories;
-        use crate::schema::circles;
-        use crate::schema::circle_goods;
-        use crate::schema::goods_character;
-        use crate::schema::characters;
-        use crate::schema::refs;
-
-        let circle_id = circle_goods::table
-            .filter(circle_goods::goods_id.eq(good.id))
-            .select(circle_goods::circle_id)
-            .first::<i32>(&mut conn)
-            .unwrap();
+    let page = page_request.page(goods, total, |good| {
+        let key = match sort.key.as_str() {
+            "name" => vec![good.name.clone().map(SortValue::from)],
+            "price" => vec![good.price.map(SortValue::from)],
+            _ => vec![],
+        };
+        (key, good.id)
+    });
 
-        let circle_name = circles::table.find(circle_id)
-            .select(circles::name)
-            .first::<Option<String>>(&mut conn)
-            .unwrap();
+    page.try_map(|goods| load_full_goods(goods, &mut conn)).map(Json)
+}
 
-        let category = categories::table
-            .filter(categories::id.eq(good.category_id))
-            .first::<Category>(&mut conn)
-            .unwrap();
+/// Builds the `FullGood` for every good with a fixed number of queries, no
+/// matter how many goods are passed in. The order of `goods` is preserved.
+pub(crate) fn load_full_goods(
+    goods: Vec<Good>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<FullGood>, CustomError> {
+    let goods = goods.into_iter().map(|good| ((), good)).collect();
+
+    Ok(load_full_goods_with(goods, conn)?
+        .into_iter()
+        .map(|((), good)| good)
+        .collect())
+}
 
-        let characters = goods_character::table
-            .left_join(characters::table.on(goods_character::character_id.eq(characters::id)))
-            .left_join(refs::table.on(characters::reference_id.eq(refs::id)))
-            .filter(goods_character::goods_id.eq(good.id))
-            .select((sql::<Integer>("characters.id"), sql::<Text>("characters.name"), sql::<Text>("refs.name")))
-            .load::<CharacterWithReference>(&mut conn)
-            .unwrap();
+/// Like `load_full_goods`, but keeps each good paired with the row data the
+/// caller loaded alongside it. A good whose circle or category row is missing
+/// fails the whole list rather than being left out of it, so that pages,
+/// bundle prices and collections never silently lose an item.
+pub(crate) fn load_full_goods_with<T>(
+    goods: Vec<(T, Good)>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<(T, FullGood)>, CustomError> {
+    use crate::schema::categories;
+    use crate::schema::characters;
+    use crate::schema::circle_goods;
+    use crate::schema::circles;
+    use crate::schema::goods_character;
+    use crate::schema::refs;
 
-        FullGood { 
-            id: good.id,
-            name: good.name.clone(), 
-            description: good.description.clone(), 
-            price: good.price, 
-            image_name: good.image_name.clone(),
-            circle_name,
-            circle_id,
-            category, 
-            characters
-        }
-    }).collect::<Vec<_>>()))
+    if goods.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let goods_ids = goods.iter().map(|(_, good)| good.id).collect::<Vec<_>>();
+    let category_ids = goods
+        .iter()
+        .map(|(_, good)| good.category_id)
+        .collect::<Vec<_>>();
+
+    let mut goods_circles = HashMap::new();
+
+    for (goods_id, circle_id, circle_name) in circle_goods::table
+        .inner_join(circles::table)
+        .filter(circle_goods::goods_id.eq_any(&goods_ids))
+        .order(circle_goods::id)
+        .select((circle_goods::goods_id, circles::id, circles::name))
+        .load::<(i32, i32, Option<String>)>(conn)
+        .map_err(handle_error)?
+    {
+        goods_circles
+            .entry(goods_id)
+            .or_insert((circle_id, circle_name));
+    }
+
+    let categories = categories::table
+        .filter(categories::id.eq_any(&category_ids))
+        .load::<Category>(conn)
+        .map_err(handle_error)?
+        .into_iter()
+        .map(|category| (category.id, category))
+        .collect::<HashMap<_, _>>();
+
+    let character_rows = goods_character::table
+        .left_join(characters::table.on(goods_character::character_id.eq(characters::id)))
+        .left_join(refs::table.on(characters::reference_id.eq(refs::id)))
+        .filter(goods_character::goods_id.eq_any(&goods_ids))
+        .order(goods_character::id)
+        .select((
+            goods_character::goods_id,
+            (sql::<Integer>("characters.id"), sql::<Text>("characters.name"), sql::<Text>("refs.name")),
+        ))
+        .load::<(i32, (i32, String, String))>(conn)
+        .map_err(handle_error)?;
+
+    let character_ids = character_rows
+        .iter()
+        .map(|(_, (id, _, _))| *id)
+        .collect::<Vec<_>>();
+    let aliases = load_aliases(&character_ids, conn)?;
+
+    let mut goods_characters: HashMap<i32, Vec<CharacterWithReference>> = HashMap::new();
+
+    for (goods_id, (id, character, reference)) in character_rows {
+        goods_characters
+            .entry(goods_id)
+            .or_default()
+            .push(CharacterWithReference {
+                aliases: aliases.get(&id).cloned().unwrap_or_default(),
+                id,
+                character,
+                reference,
+            });
+    }
+
+    goods
+        .into_iter()
+        .map(|(data, good)| {
+            let inconsistent = |missing: &str| {
+                Custom(
+                    Status::InternalServerError,
+                    Json(ErrorInfo::new(format!(
+                        "Goods {} has no {}",
+                        good.id, missing
+                    ))),
+                )
+            };
+            let (circle_id, circle_name) = goods_circles
+                .get(&good.id)
+                .cloned()
+                .ok_or_else(|| inconsistent("circle"))?;
+            let category = categories
+                .get(&good.category_id)
+                .cloned()
+                .ok_or_else(|| inconsistent("category"))?;
+
+            Ok((
+                data,
+                FullGood {
+                    characters: goods_characters.remove(&good.id).unwrap_or_default(),
+                    id: good.id,
+                    name: good.name,
+                    description: good.description,
+                    price: good.price,
+                    image_name: good.image_name,
+                    circle_name,
+                    circle_id,
+                    stock: good.stock,
+                    availability: good.availability,
+                    category,
+                },
+            ))
+        })
+        .collect()
 }
 
 #[get("/goods/<goods_id>")]
@@ -198,6 +603,7 @@ pub fn patch_goods(
     user: AuthenticatedUser,
     goods_id: i32,
     update_goods: Json<UpdateGood>,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<Json<Good>, CustomError> {
     use crate::schema::circle_goods;
@@ -218,6 +624,8 @@ pub fn patch_goods(
         .execute(&mut conn)
         .map_err(handle_error)?;
 
+    feed.publish(ChangeKind::goods, ChangeAction::updated, goods_id, circle_id);
+
     goods
         .find(goods_id)
         .first(&mut conn)
@@ -225,10 +633,44 @@ pub fn patch_goods(
         .map_err(handle_error)
 }
 
+#[patch("/goods/<goods_id>/stock", format = "json", data = "<update_stock>")]
+pub fn patch_goods_stock(
+    user: AuthenticatedUser,
+    goods_id: i32,
+    update_stock: Json<UpdateStock>,
+    feed: &rocket::State<ChangeFeed>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Good>, CustomError> {
+    let (stock, availability) = update_stock.into_inner().resolve()?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let circle_id = circle_goods::table
+        .filter(circle_goods::goods_id.eq(goods_id))
+        .select(circle_goods::circle_id)
+        .first::<i32>(&mut conn)
+        .map_err(handle_error)?;
+
+    user.check_permission(circle_id)?;
+
+    let good = diesel::update(goods::table.find(goods_id))
+        .set((
+            stock.map(|stock| goods::stock.eq(stock)),
+            availability.map(|availability| goods::availability.eq(availability)),
+        ))
+        .get_result::<Good>(&mut conn)
+        .map_err(handle_error)?;
+
+    feed.publish(ChangeKind::goods, ChangeAction::updated, goods_id, circle_id);
+
+    Ok(Json(good))
+}
+
 #[delete("/goods/<goods_id>")]
 pub fn delete_goods(
     user: AuthenticatedUser,
     goods_id: i32,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<(), CustomError> {
     use crate::schema::circle_goods;
@@ -254,6 +696,8 @@ pub fn delete_goods(
             Json(ErrorInfo::new("not_found".to_string())),
         ))
     } else {
+        feed.publish(ChangeKind::goods, ChangeAction::deleted, goods_id, circle_id);
+
         Ok(())
     }
 }
@@ -280,6 +724,7 @@ pub fn post_good_character(
     user: AuthenticatedUser,
     goods_id: i32,
     new_character_id: Json<NewCharacterId>,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<Created<()>, CustomError> {
     use crate::schema::circle_goods;
@@ -303,6 +748,8 @@ pub fn post_good_character(
         .execute(&mut conn)
         .map_err(handle_error)?;
 
+    feed.publish(ChangeKind::goods, ChangeAction::updated, goods_id, circle_id);
+
     Ok(Created::new(format!(
         "/goods/{}/characters/{}",
         goods_id, new_character_id.character_id
@@ -314,6 +761,7 @@ pub fn delete_good_character(
     user: AuthenticatedUser,
     goods_id: i32,
     character_id: i32,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<(), CustomError> {
     use crate::schema::circle_goods;
@@ -343,6 +791,79 @@ pub fn delete_good_character(
             Json(ErrorInfo::new("not_found".to_string())),
         ))
     } else {
+        feed.publish(ChangeKind::goods, ChangeAction::updated, goods_id, circle_id);
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::test_support::{
+        count_queries, insert_category, insert_character, insert_circle, insert_goods,
+        stop_counting, test_connection, TestConnection,
+    };
+
+    fn load(conn: &mut TestConnection, goods_ids: &[i32]) -> Vec<Good> {
+        goods::table
+            .filter(goods::id.eq_any(goods_ids))
+            .order(goods::id)
+            .load::<Good>(conn)
+            .expect("Failed to load goods")
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn full_goods_take_the_same_number_of_queries_for_twice_the_goods() {
+        let mut conn = test_connection();
+        let circle_id = insert_circle(&mut conn);
+        let category_id = insert_category(&mut conn);
+        let character_id = insert_character(&mut conn);
+
+        let mut queries_for = |count: usize| {
+            let ids = insert_goods(&mut conn, count, circle_id, category_id, character_id);
+            let goods = load(&mut conn, &ids);
+
+            let queries = count_queries(&mut conn);
+            let full_goods = load_full_goods(goods, &mut conn).unwrap();
+            stop_counting(&mut conn);
+
+            assert_eq!(full_goods.len(), count);
+            for good in &full_goods {
+                assert_eq!(good.circle_id, circle_id);
+                assert_eq!(good.characters.len(), 1);
+                assert_eq!(good.characters[0].aliases.len(), 1);
+            }
+            queries.load(Ordering::SeqCst)
+        };
+
+        let few = queries_for(10);
+        let many = queries_for(20);
+
+        assert!(few > 0);
+        assert_eq!(few, many);
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn goods_without_a_circle_fail_the_list() {
+        let mut conn = test_connection();
+        let circle_id = insert_circle(&mut conn);
+        let category_id = insert_category(&mut conn);
+        let character_id = insert_character(&mut conn);
+        let ids = insert_goods(&mut conn, 3, circle_id, category_id, character_id);
+
+        diesel::delete(circle_goods::table.filter(circle_goods::goods_id.eq(ids[1])))
+            .execute(&mut conn)
+            .unwrap();
+
+        let goods = load(&mut conn, &ids);
+        let Err(Custom(status, _)) = load_full_goods(goods, &mut conn) else {
+            panic!("goods without a circle were loaded");
+        };
+        assert_eq!(status, Status::InternalServerError);
+    }
+}
@@ -87,10 +87,8 @@ pub async fn get_image(filename: String) -> Result<CachedFile, CustomError> {
         return Err(Custom(Status::BadRequest, Json(ErrorInfo::new("invalid filename".into()))));
     }
 
-    Ok(
-        NamedFile::open(format!("images/{}/{}.webp", &filename[..2], filename))
-            .await
-            .map_err(|_| Custom(Status::NotFound, Json(ErrorInfo::new("file not found".into()))))
-            .map(|file| CachedFile(file))?
-    )
+    NamedFile::open(format!("images/{}/{}.webp", &filename[..2], filename))
+        .await
+        .map_err(|_| Custom(Status::NotFound, Json(ErrorInfo::new("file not found".into()))))
+        .map(CachedFile)
 }
\ No newline at end of file
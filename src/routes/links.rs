@@ -2,8 +2,13 @@ use std::time::SystemTime;
 
 use crate::error_handler::{handle_error, CustomError, ErrorInfo};
 use crate::models::{AuthenticatedUser, Link, LinkTypeEnum};
+use crate::routes::stream::{ChangeAction, ChangeFeed, ChangeKind};
+use crate::schema::{circle_links, links};
+use crate::utils::pagination::{Page, PageRequest, Sort, SortValue};
 use crate::DbPool;
 
+use diesel::dsl::{count_distinct, IntoBoxed, LeftJoinOn};
+use diesel::pg::Pg;
 use diesel::prelude::*;
 use rocket::http::Status;
 use rocket::response::status::{Created, Custom};
@@ -42,6 +47,7 @@ pub fn post_circle_link(
     user: AuthenticatedUser,
     circle_id: i32,
     new_link: Json<NewLink>,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<Created<Json<Link>>, CustomError> {
     use crate::schema::circle_links;
@@ -64,18 +70,19 @@ pub fn post_circle_link(
         .execute(&mut conn)
         .map_err(handle_error)?;
 
+    feed.publish(ChangeKind::link, ChangeAction::created, link.id, circle_id);
+
     Ok(Created::new(format!("/links/{}", link.id)).body(Json(link)))
 }
 
-#[get("/links?<circle_id>")]
-pub fn get_links(
-    circle_id: Option<i32>,
-    pool: &rocket::State<DbPool>,
-) -> Result<Json<Vec<Link>>, CustomError> {
-    use crate::schema::circle_links;
-    use crate::schema::links;
+type LinksQuery = IntoBoxed<
+    'static,
+    LeftJoinOn<links::table, circle_links::table, diesel::dsl::Eq<links::id, circle_links::link_id>>,
+    Pg,
+>;
 
-    let mut conn = pool.get().expect("Failed to get database connection");
+fn links_query(circle_id: Option<i32>, event_id: Option<i32>) -> LinksQuery {
+    use crate::schema::circle_events;
 
     let mut query = links::table
         .left_join(circle_links::table.on(links::id.eq(circle_links::link_id)))
@@ -85,12 +92,65 @@ pub fn get_links(
         query = query.filter(circle_links::circle_id.eq(circle_id));
     }
 
+    if let Some(event_id) = event_id {
+        query = query.filter(
+            circle_links::circle_id.eq_any(
+                circle_events::table
+                    .filter(circle_events::event_id.eq(event_id))
+                    .select(circle_events::circle_id),
+            ),
+        );
+    }
+
     query
+}
+
+#[get("/links?<circle_id>&<event_id>&<limit>&<cursor>&<sort>")]
+pub fn get_links(
+    circle_id: Option<i32>,
+    event_id: Option<i32>,
+    limit: Option<i64>,
+    cursor: Option<String>,
+    sort: Option<String>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Page<Link>>, CustomError> {
+    let sort = Sort::new(sort, "links.id", &[("name", &["links.name"])])?;
+    let page_request = PageRequest::new(limit, cursor.as_deref(), &sort)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let total = links_query(circle_id, event_id)
+        .select(count_distinct(links::id))
+        .first::<i64>(&mut conn)
+        .map_err(handle_error)?;
+
+    let mut query = links_query(circle_id, event_id);
+
+    query = match (sort.key.as_str(), sort.descending) {
+        ("name", false) => query.order_by((links::name.asc(), links::id.asc())),
+        ("name", true) => query.order_by((links::name.desc(), links::id.desc())),
+        (_, false) => query.order_by(links::id.asc()),
+        (_, true) => query.order_by(links::id.desc()),
+    };
+
+    if let Some(after) = page_request.after() {
+        query = query.filter(after);
+    }
+
+    let links = query
         .select(links::all_columns)
         .distinct()
+        .limit(page_request.fetch_limit())
         .load::<Link>(&mut conn)
-        .map(Json)
-        .map_err(handle_error)
+        .map_err(handle_error)?;
+
+    Ok(Json(page_request.page(links, total, |link| {
+        let key = match sort.key.as_str() {
+            "name" => vec![link.name.clone().map(SortValue::from)],
+            _ => vec![],
+        };
+        (key, link.id)
+    })))
 }
 
 #[get("/links/<link_id>")]
@@ -114,6 +174,7 @@ pub fn patch_link(
     user: AuthenticatedUser,
     link_id: i32,
     link_request: Json<UpdateLink>,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<Json<Link>, CustomError> {
     use crate::schema::circle_links;
@@ -134,6 +195,8 @@ pub fn patch_link(
         .execute(&mut conn)
         .map_err(handle_error)?;
 
+    feed.publish(ChangeKind::link, ChangeAction::updated, link_id, circle_id);
+
     links
         .find(link_id)
         .first(&mut conn)
@@ -145,6 +208,7 @@ pub fn patch_link(
 pub fn delete_link(
     user: AuthenticatedUser,
     link_id: i32,
+    feed: &rocket::State<ChangeFeed>,
     pool: &rocket::State<DbPool>,
 ) -> Result<(), CustomError> {
     use crate::schema::circle_links;
@@ -174,6 +238,8 @@ pub fn delete_link(
             Json(ErrorInfo::new("not_found".to_string())),
         ))
     } else {
+        feed.publish(ChangeKind::link, ChangeAction::deleted, link_id, circle_id);
+
         Ok(())
     }
 }
@@ -4,7 +4,19 @@ pub(crate) mod bundles;
 pub(crate) mod categories;
 pub(crate) mod characters;
 pub(crate) mod circles;
+pub(crate) mod collections;
+pub(crate) mod events;
 pub(crate) mod goods;
 pub(crate) mod images;
 pub(crate) mod links;
+pub(crate) mod orders;
+pub(crate) mod planner;
+pub(crate) mod prepayments;
+pub(crate) mod reconciliations;
 pub(crate) mod references;
+pub(crate) mod sales;
+pub(crate) mod search;
+pub(crate) mod stream;
+pub(crate) mod surveys;
+pub(crate) mod tallies;
+pub(crate) mod wishlists;
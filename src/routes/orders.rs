@@ -0,0 +1,760 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::SystemTime;
+
+use crate::error_handler::{handle_error, CustomError, ErrorInfo};
+use crate::models::{AuthenticatedUser, Order, OrderForm, OrderFormItem};
+use crate::DbPool;
+
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use rocket::http::Status;
+use rocket::response::status::{Created, Custom};
+use rocket::serde::json::Json;
+use rocket::serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Insertable, Deserialize)]
+#[diesel(table_name = crate::schema::order_forms)]
+pub struct NewOrderForm {
+    pub title: String,
+    pub description: Option<String>,
+    pub opens_at: SystemTime,
+    pub closes_at: SystemTime,
+}
+
+#[derive(Deserialize, AsChangeset)]
+#[diesel(table_name = crate::schema::order_forms)]
+pub struct UpdateOrderForm {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub opens_at: Option<SystemTime>,
+    pub closes_at: Option<SystemTime>,
+}
+
+#[derive(Insertable, Deserialize)]
+#[diesel(table_name = crate::schema::order_form_items)]
+pub struct NewOrderFormItem {
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub max_quantity: Option<i32>,
+}
+
+/// `max_quantity: null` lifts the limit.
+#[derive(Deserialize, AsChangeset)]
+#[diesel(table_name = crate::schema::order_form_items, treat_none_as_null = true)]
+pub struct UpdateOrderFormItem {
+    pub max_quantity: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct NewOrder {
+    pub note: Option<String>,
+    pub items: Vec<NewOrderItem>,
+}
+
+#[derive(Deserialize)]
+pub struct NewOrderItem {
+    pub order_form_item_id: i32,
+    pub quantity: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::orders)]
+pub struct InsertOrder {
+    pub order_form_id: i32,
+    pub user_id: i32,
+    pub note: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::order_items)]
+pub struct InsertOrderItem {
+    pub order_id: i32,
+    pub order_form_item_id: i32,
+    pub quantity: i32,
+    pub price: i32,
+}
+
+#[derive(Serialize)]
+pub struct OrderFormItemInfo {
+    pub id: i32,
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub name: Option<String>,
+    pub price: Option<i32>,
+    pub max_quantity: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct FullOrderForm {
+    pub id: i32,
+    pub circle_id: i32,
+    pub title: String,
+    pub description: Option<String>,
+    pub opens_at: SystemTime,
+    pub closes_at: SystemTime,
+    pub is_open: bool,
+    pub items: Vec<OrderFormItemInfo>,
+}
+
+#[derive(Serialize)]
+pub struct OrderLine {
+    pub order_form_item_id: i32,
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub name: Option<String>,
+    pub quantity: i32,
+    pub price: i32,
+    pub subtotal: i64,
+}
+
+#[derive(Serialize)]
+pub struct FullOrder {
+    pub id: i32,
+    pub order_form_id: i32,
+    pub user_id: i32,
+    pub nickname: String,
+    pub note: Option<String>,
+    pub created_at: SystemTime,
+    pub items: Vec<OrderLine>,
+    pub total: i64,
+}
+
+#[derive(Serialize)]
+pub struct OrderItemTotal {
+    pub order_form_item_id: i32,
+    pub name: Option<String>,
+    pub quantity: i64,
+    pub amount: i64,
+}
+
+#[derive(Serialize)]
+pub struct OrderFormOrders {
+    pub orders: Vec<FullOrder>,
+    pub items: Vec<OrderItemTotal>,
+    pub total: i64,
+}
+
+fn find_order_form(
+    order_form_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<OrderForm, CustomError> {
+    use crate::schema::order_forms;
+
+    order_forms::table
+        .find(order_form_id)
+        .first::<OrderForm>(conn)
+        .map_err(handle_error)
+}
+
+/// Looks up an order form and checks that `user` may manage its circle.
+pub(crate) fn find_managed_order_form(
+    user: &AuthenticatedUser,
+    order_form_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<OrderForm, CustomError> {
+    let order_form = find_order_form(order_form_id, conn)?;
+
+    user.check_permission(order_form.circle_id)?;
+
+    Ok(order_form)
+}
+
+/// Checks that exactly one of `goods_id` and `bundle_id` is given and that it
+/// belongs to `circle_id`.
+pub(crate) fn check_circle_item(
+    circle_id: i32,
+    goods_id: Option<i32>,
+    bundle_id: Option<i32>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<(), CustomError> {
+    use crate::schema::circle_bundles;
+    use crate::schema::circle_goods;
+
+    let owned = match (goods_id, bundle_id) {
+        (Some(goods_id), None) => circle_goods::table
+            .filter(circle_goods::circle_id.eq(circle_id))
+            .filter(circle_goods::goods_id.eq(goods_id))
+            .count()
+            .get_result::<i64>(conn)
+            .map_err(handle_error)?,
+        (None, Some(bundle_id)) => circle_bundles::table
+            .filter(circle_bundles::circle_id.eq(circle_id))
+            .filter(circle_bundles::bundle_id.eq(bundle_id))
+            .count()
+            .get_result::<i64>(conn)
+            .map_err(handle_error)?,
+        _ => {
+            return Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new(
+                    "Either goods_id or bundle_id must be given".into(),
+                )),
+            ))
+        }
+    };
+
+    if owned == 0 {
+        Err(Custom(
+            Status::BadRequest,
+            Json(ErrorInfo::new("Item does not belong to this circle".into())),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn load_order_form_items(
+    order_form_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<OrderFormItemInfo>, CustomError> {
+    use crate::schema::bundles;
+    use crate::schema::goods;
+    use crate::schema::order_form_items;
+
+    Ok(order_form_items::table
+        .left_join(goods::table)
+        .left_join(bundles::table)
+        .filter(order_form_items::order_form_id.eq(order_form_id))
+        .order(order_form_items::id)
+        .select((
+            order_form_items::all_columns,
+            goods::name.nullable(),
+            goods::price.nullable(),
+            bundles::name.nullable(),
+            bundles::price.nullable(),
+        ))
+        .load::<(
+            OrderFormItem,
+            Option<String>,
+            Option<i32>,
+            Option<String>,
+            Option<i32>,
+        )>(conn)
+        .map_err(handle_error)?
+        .into_iter()
+        .map(
+            |(item, goods_name, goods_price, bundle_name, bundle_price)| OrderFormItemInfo {
+                id: item.id,
+                goods_id: item.goods_id,
+                bundle_id: item.bundle_id,
+                name: goods_name.or(bundle_name),
+                price: goods_price.or(bundle_price),
+                max_quantity: item.max_quantity,
+            },
+        )
+        .collect())
+}
+
+/// Attaches the ordered items to every order, in a fixed number of queries.
+fn load_full_orders(
+    orders: Vec<(Order, String)>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<FullOrder>, CustomError> {
+    use crate::schema::bundles;
+    use crate::schema::goods;
+    use crate::schema::order_form_items;
+    use crate::schema::order_items;
+
+    let order_ids = orders.iter().map(|(order, _)| order.id).collect::<Vec<_>>();
+
+    let rows = order_items::table
+        .inner_join(
+            order_form_items::table
+                .left_join(goods::table)
+                .left_join(bundles::table),
+        )
+        .filter(order_items::order_id.eq_any(&order_ids))
+        .order(order_items::id)
+        .select((
+            order_items::order_id,
+            order_items::order_form_item_id,
+            order_form_items::goods_id,
+            order_form_items::bundle_id,
+            goods::name.nullable(),
+            bundles::name.nullable(),
+            order_items::quantity,
+            order_items::price,
+        ))
+        .load::<(
+            i32,
+            i32,
+            Option<i32>,
+            Option<i32>,
+            Option<String>,
+            Option<String>,
+            i32,
+            i32,
+        )>(conn)
+        .map_err(handle_error)?;
+
+    let mut lines: HashMap<i32, Vec<OrderLine>> = HashMap::new();
+
+    for (
+        order_id,
+        order_form_item_id,
+        goods_id,
+        bundle_id,
+        goods_name,
+        bundle_name,
+        quantity,
+        price,
+    ) in rows
+    {
+        lines.entry(order_id).or_default().push(OrderLine {
+            order_form_item_id,
+            goods_id,
+            bundle_id,
+            name: goods_name.or(bundle_name),
+            quantity,
+            price,
+            subtotal: i64::from(quantity) * i64::from(price),
+        });
+    }
+
+    Ok(orders
+        .into_iter()
+        .map(|(order, nickname)| {
+            let items = lines.remove(&order.id).unwrap_or_default();
+
+            FullOrder {
+                id: order.id,
+                order_form_id: order.order_form_id,
+                user_id: order.user_id,
+                nickname,
+                note: order.note,
+                created_at: order.created_at,
+                total: items.iter().map(|item| item.subtotal).sum(),
+                items,
+            }
+        })
+        .collect())
+}
+
+#[post(
+    "/circles/<circle_id>/order-forms",
+    format = "json",
+    data = "<new_order_form>"
+)]
+pub fn post_order_form(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    new_order_form: Json<NewOrderForm>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<Json<OrderForm>>, CustomError> {
+    use crate::schema::order_forms;
+
+    user.check_permission(circle_id)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let order_form = diesel::insert_into(order_forms::table)
+        .values((
+            order_forms::circle_id.eq(circle_id),
+            new_order_form.into_inner(),
+        ))
+        .get_result::<OrderForm>(&mut conn)
+        .map_err(handle_error)?;
+
+    Ok(Created::new(format!("/order-forms/{}", order_form.id)).body(Json(order_form)))
+}
+
+#[get("/circles/<circle_id>/order-forms")]
+pub fn get_circle_order_forms(
+    circle_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Vec<OrderForm>>, CustomError> {
+    use crate::schema::order_forms;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    order_forms::table
+        .filter(order_forms::circle_id.eq(circle_id))
+        .order((order_forms::opens_at.desc(), order_forms::id))
+        .load::<OrderForm>(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
+
+#[get("/order-forms/<order_form_id>")]
+pub fn get_order_form_by_id(
+    order_form_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<FullOrderForm>, CustomError> {
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let order_form = find_order_form(order_form_id, &mut conn)?;
+    let items = load_order_form_items(order_form.id, &mut conn)?;
+
+    Ok(Json(FullOrderForm {
+        is_open: order_form.is_open(),
+        id: order_form.id,
+        circle_id: order_form.circle_id,
+        title: order_form.title,
+        description: order_form.description,
+        opens_at: order_form.opens_at,
+        closes_at: order_form.closes_at,
+        items,
+    }))
+}
+
+#[patch(
+    "/order-forms/<order_form_id>",
+    format = "json",
+    data = "<update_order_form>"
+)]
+pub fn patch_order_form(
+    user: AuthenticatedUser,
+    order_form_id: i32,
+    update_order_form: Json<UpdateOrderForm>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<OrderForm>, CustomError> {
+    use crate::schema::order_forms;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_managed_order_form(&user, order_form_id, &mut conn)?;
+
+    diesel::update(order_forms::table.find(order_form_id))
+        .set(update_order_form.into_inner())
+        .get_result::<OrderForm>(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
+
+#[delete("/order-forms/<order_form_id>")]
+pub fn delete_order_form(
+    user: AuthenticatedUser,
+    order_form_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::order_forms;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_managed_order_form(&user, order_form_id, &mut conn)?;
+
+    let size = diesel::delete(order_forms::table.find(order_form_id))
+        .execute(&mut conn)
+        .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[post(
+    "/order-forms/<order_form_id>/items",
+    format = "json",
+    data = "<new_item>"
+)]
+pub fn post_order_form_item(
+    user: AuthenticatedUser,
+    order_form_id: i32,
+    new_item: Json<NewOrderFormItem>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<Json<OrderFormItem>>, CustomError> {
+    use crate::schema::order_form_items;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let order_form = find_managed_order_form(&user, order_form_id, &mut conn)?;
+
+    check_circle_item(
+        order_form.circle_id,
+        new_item.goods_id,
+        new_item.bundle_id,
+        &mut conn,
+    )?;
+
+    let item = diesel::insert_into(order_form_items::table)
+        .values((
+            order_form_items::order_form_id.eq(order_form_id),
+            new_item.into_inner(),
+        ))
+        .get_result::<OrderFormItem>(&mut conn)
+        .map_err(handle_error)?;
+
+    Ok(Created::new(format!("/order-forms/{}/items/{}", order_form_id, item.id)).body(Json(item)))
+}
+
+#[patch(
+    "/order-forms/<order_form_id>/items/<item_id>",
+    format = "json",
+    data = "<update_item>"
+)]
+pub fn patch_order_form_item(
+    user: AuthenticatedUser,
+    order_form_id: i32,
+    item_id: i32,
+    update_item: Json<UpdateOrderFormItem>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<OrderFormItem>, CustomError> {
+    use crate::schema::order_form_items;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_managed_order_form(&user, order_form_id, &mut conn)?;
+
+    diesel::update(
+        order_form_items::table
+            .find(item_id)
+            .filter(order_form_items::order_form_id.eq(order_form_id)),
+    )
+    .set(update_item.into_inner())
+    .get_result::<OrderFormItem>(&mut conn)
+    .map(Json)
+    .map_err(handle_error)
+}
+
+#[delete("/order-forms/<order_form_id>/items/<item_id>")]
+pub fn delete_order_form_item(
+    user: AuthenticatedUser,
+    order_form_id: i32,
+    item_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::order_form_items;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_managed_order_form(&user, order_form_id, &mut conn)?;
+
+    let size = diesel::delete(
+        order_form_items::table
+            .find(item_id)
+            .filter(order_form_items::order_form_id.eq(order_form_id)),
+    )
+    .execute(&mut conn)
+    .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Places the user's order. Each user gets one order per form; it can be
+/// cancelled and placed again while the form is open.
+#[post(
+    "/order-forms/<order_form_id>/orders",
+    format = "json",
+    data = "<new_order>"
+)]
+pub fn post_order(
+    user: AuthenticatedUser,
+    order_form_id: i32,
+    new_order: Json<NewOrder>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<Json<FullOrder>>, CustomError> {
+    use crate::schema::order_items;
+    use crate::schema::orders;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let order_form = find_order_form(order_form_id, &mut conn)?;
+
+    if !order_form.is_open() {
+        return Err(Custom(
+            Status::Conflict,
+            Json(ErrorInfo::new("Order form is not open".into())),
+        ));
+    }
+
+    let new_order = new_order.into_inner();
+
+    if new_order.items.is_empty() {
+        return Err(Custom(
+            Status::BadRequest,
+            Json(ErrorInfo::new("Order is empty".into())),
+        ));
+    }
+
+    let form_items = load_order_form_items(order_form.id, &mut conn)?
+        .into_iter()
+        .map(|item| (item.id, item))
+        .collect::<HashMap<_, _>>();
+
+    let mut seen = HashSet::new();
+
+    for line in &new_order.items {
+        let item = form_items.get(&line.order_form_item_id).ok_or_else(|| {
+            Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new(format!(
+                    "Unknown order form item {}",
+                    line.order_form_item_id
+                ))),
+            )
+        })?;
+
+        if !seen.insert(item.id) {
+            return Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new(format!(
+                    "Order form item {} is listed twice",
+                    item.id
+                ))),
+            ));
+        }
+
+        if line.quantity < 1 {
+            return Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new("Quantity must be at least 1".into())),
+            ));
+        }
+
+        if let Some(max_quantity) = item.max_quantity.filter(|max| line.quantity > *max) {
+            return Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new(format!(
+                    "At most {} of order form item {} can be ordered",
+                    max_quantity, item.id
+                ))),
+            ));
+        }
+    }
+
+    let order = conn
+        .transaction(|conn| {
+            let order = diesel::insert_into(orders::table)
+                .values(InsertOrder {
+                    order_form_id,
+                    user_id: user.id,
+                    note: new_order.note,
+                })
+                .get_result::<Order>(conn)?;
+
+            diesel::insert_into(order_items::table)
+                .values(
+                    new_order
+                        .items
+                        .iter()
+                        .map(|line| InsertOrderItem {
+                            order_id: order.id,
+                            order_form_item_id: line.order_form_item_id,
+                            quantity: line.quantity,
+                            price: form_items[&line.order_form_item_id].price.unwrap_or(0),
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .execute(conn)?;
+
+            Ok(order)
+        })
+        .map_err(handle_error)?;
+
+    let full_order = load_full_orders(vec![(order, user.nickname)], &mut conn)?
+        .pop()
+        .expect("Order was just inserted");
+
+    Ok(Created::new("/users/me/orders").body(Json(full_order)))
+}
+
+#[get("/order-forms/<order_form_id>/orders")]
+pub fn get_order_form_orders(
+    user: AuthenticatedUser,
+    order_form_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<OrderFormOrders>, CustomError> {
+    use crate::schema::orders;
+    use crate::schema::users;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_managed_order_form(&user, order_form_id, &mut conn)?;
+
+    let orders = orders::table
+        .inner_join(users::table)
+        .filter(orders::order_form_id.eq(order_form_id))
+        .order((orders::created_at, orders::id))
+        .select((orders::all_columns, users::nickname))
+        .load::<(Order, String)>(&mut conn)
+        .map_err(handle_error)?;
+
+    let orders = load_full_orders(orders, &mut conn)?;
+
+    let mut items = BTreeMap::new();
+
+    for line in orders.iter().flat_map(|order| &order.items) {
+        let total = items
+            .entry(line.order_form_item_id)
+            .or_insert_with(|| OrderItemTotal {
+                order_form_item_id: line.order_form_item_id,
+                name: line.name.clone(),
+                quantity: 0,
+                amount: 0,
+            });
+
+        total.quantity += i64::from(line.quantity);
+        total.amount += line.subtotal;
+    }
+
+    Ok(Json(OrderFormOrders {
+        total: orders.iter().map(|order| order.total).sum(),
+        items: items.into_values().collect(),
+        orders,
+    }))
+}
+
+#[get("/users/me/orders")]
+pub fn get_my_orders(
+    user: AuthenticatedUser,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Vec<FullOrder>>, CustomError> {
+    use crate::schema::orders;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let orders = orders::table
+        .filter(orders::user_id.eq(user.id))
+        .order((orders::created_at.desc(), orders::id))
+        .load::<Order>(&mut conn)
+        .map_err(handle_error)?
+        .into_iter()
+        .map(|order| (order, user.nickname.clone()))
+        .collect();
+
+    load_full_orders(orders, &mut conn).map(Json)
+}
+
+/// Cancels the user's own order, as long as its form is still open.
+#[delete("/orders/<order_id>")]
+pub fn delete_order(
+    user: AuthenticatedUser,
+    order_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::orders;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let order = orders::table
+        .find(order_id)
+        .filter(orders::user_id.eq(user.id))
+        .first::<Order>(&mut conn)
+        .map_err(handle_error)?;
+
+    if !find_order_form(order.order_form_id, &mut conn)?.is_open() {
+        return Err(Custom(
+            Status::Conflict,
+            Json(ErrorInfo::new("Order form is not open".into())),
+        ));
+    }
+
+    diesel::delete(orders::table.find(order.id))
+        .execute(&mut conn)
+        .map_err(handle_error)?;
+
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use crate::error_handler::{handle_error, CustomError};
+use crate::utils::booth::{serpentine, Booth};
+use crate::DbPool;
+
+use diesel::prelude::*;
+use rocket::serde::json::Json;
+use rocket::serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Deserialize)]
+pub struct RouteRequest {
+    pub circle_ids: Vec<i32>,
+    #[serde(default)]
+    pub priority: Vec<i32>,
+    pub event_id: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct RouteStop {
+    pub step: usize,
+    pub circle_id: i32,
+    pub circle_name: Option<String>,
+    pub location: Option<String>,
+    pub priority: bool,
+}
+
+type CircleRow = (i32, Option<String>, Option<String>);
+
+/// Plans a walk through the requested booths. Priority circles are visited
+/// first, then the rest continue from the end of the priority walk. Circles
+/// whose booth cannot be parsed are appended in the order they were given.
+#[post("/circles/route", format = "json", data = "<route_request>")]
+pub fn post_circle_route(
+    route_request: Json<RouteRequest>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Vec<RouteStop>>, CustomError> {
+    use crate::schema::circle_events;
+    use crate::schema::circles;
+
+    let route_request = route_request.into_inner();
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let rows = match route_request.event_id {
+        Some(event_id) => circles::table
+            .inner_join(circle_events::table)
+            .filter(circle_events::event_id.eq(event_id))
+            .filter(circles::id.eq_any(&route_request.circle_ids))
+            .select((circles::id, circles::name, circle_events::location))
+            .load::<CircleRow>(&mut conn),
+        None => circles::table
+            .filter(circles::id.eq_any(&route_request.circle_ids))
+            .select((circles::id, circles::name, circles::location))
+            .load::<CircleRow>(&mut conn),
+    }
+    .map_err(handle_error)?;
+
+    let mut rows = rows
+        .into_iter()
+        .map(|row| (row.0, row))
+        .collect::<HashMap<_, _>>();
+
+    let rows = route_request
+        .circle_ids
+        .iter()
+        .filter_map(|circle_id| rows.remove(circle_id))
+        .collect();
+
+    Ok(Json(plan_route(rows, &route_request.priority)))
+}
+
+/// Orders `rows`, given in the order they were requested, into the stops of
+/// the walk described at [`post_circle_route`].
+fn plan_route(rows: Vec<CircleRow>, priority: &[i32]) -> Vec<RouteStop> {
+    let mut priority_stops = vec![];
+    let mut other_stops = vec![];
+    let mut unplaced = vec![];
+
+    for row in rows {
+        let is_priority = priority.contains(&row.0);
+
+        match row.2.as_deref().map(str::parse::<Booth>) {
+            Some(Ok(booth)) if is_priority => priority_stops.push((booth, (row, true))),
+            Some(Ok(booth)) => other_stops.push((booth, (row, false))),
+            _ => unplaced.push((row, is_priority)),
+        }
+    }
+
+    let priority_stops = serpentine(priority_stops, false);
+
+    let reverse = match priority_stops.last() {
+        Some((last, _)) => {
+            other_stops.sort_by(|(a, _), (b, _)| a.cmp(b));
+            other_stops.partition_point(|(booth, _)| booth < last) > other_stops.len() / 2
+        }
+        None => false,
+    };
+
+    let other_stops = serpentine(other_stops, reverse);
+
+    priority_stops
+        .into_iter()
+        .chain(other_stops)
+        .map(|(_, stop)| stop)
+        .chain(unplaced)
+        .enumerate()
+        .map(
+            |(step, ((circle_id, circle_name, location), priority))| RouteStop {
+                step: step + 1,
+                circle_id,
+                circle_name,
+                location,
+                priority,
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Circles numbered from 1 in the order of `locations`.
+    fn rows(locations: &[&str]) -> Vec<CircleRow> {
+        locations
+            .iter()
+            .enumerate()
+            .map(|(i, location)| (i as i32 + 1, None, Some(location.to_string())))
+            .collect()
+    }
+
+    fn order(route: &[RouteStop]) -> Vec<i32> {
+        route.iter().map(|stop| stop.circle_id).collect()
+    }
+
+    #[test]
+    fn visits_priority_circles_first() {
+        let route = plan_route(rows(&["A-1", "A-2", "A-3"]), &[3]);
+
+        assert_eq!(order(&route)[0], 3);
+        assert!(route[0].priority);
+        assert!(!route[1].priority);
+        assert_eq!(
+            route.iter().map(|stop| stop.step).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn continues_from_the_end_of_the_priority_walk() {
+        // Ending at B-9, past most of the others, the rest are walked
+        // backwards from there.
+        let route = plan_route(rows(&["A-1", "A-2", "B-1", "B-8", "B-9"]), &[5]);
+        assert_eq!(order(&route), [5, 4, 3, 1, 2]);
+
+        // Ending at A-1, they are walked forwards.
+        let route = plan_route(rows(&["A-1", "A-2", "B-1", "B-8", "B-9"]), &[1]);
+        assert_eq!(order(&route), [1, 2, 5, 4, 3]);
+    }
+
+    #[test]
+    fn puts_circles_without_a_booth_last_in_the_given_order() {
+        let mut circles = rows(&["?", "B-1", "somewhere", "A-1"]);
+        circles.push((5, None, None));
+
+        let route = plan_route(circles, &[3]);
+
+        assert_eq!(order(&route), [4, 2, 1, 3, 5]);
+        assert!(route[3].priority);
+    }
+}
@@ -0,0 +1,560 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use crate::error_handler::{handle_error, CustomError, ErrorInfo};
+use crate::models::{AuthenticatedUser, Prepayment};
+use crate::routes::orders::{check_circle_item, find_managed_order_form};
+use crate::utils::strings::generate_code;
+use crate::DbPool;
+
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use rocket::http::Status;
+use rocket::response::status::{Created, Custom};
+use rocket::serde::json::Json;
+use rocket::serde::Deserialize;
+use serde::Serialize;
+
+const CODE_LENGTH: usize = 8;
+
+/// Put in front of the pickup code in QR payloads. Together with the code it
+/// stays within the QR alphanumeric character set, which keeps the symbol
+/// small enough to scan off a phone.
+const QR_PREFIX: &str = "NEON-PICKUP:";
+
+#[derive(Deserialize)]
+pub struct NewPrepayment {
+    pub buyer_name: String,
+    pub contact: Option<String>,
+    pub note: Option<String>,
+    pub items: Vec<NewPrepaymentItem>,
+}
+
+#[derive(Deserialize)]
+pub struct NewPrepaymentItem {
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub quantity: i32,
+}
+
+#[derive(Deserialize, AsChangeset)]
+#[diesel(table_name = crate::schema::prepayments)]
+pub struct UpdatePrepayment {
+    pub buyer_name: Option<String>,
+    pub contact: Option<String>,
+    pub note: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::prepayments)]
+pub struct InsertPrepayment {
+    pub circle_id: i32,
+    pub buyer_name: String,
+    pub contact: Option<String>,
+    pub note: Option<String>,
+    pub user_id: Option<i32>,
+    pub order_id: Option<i32>,
+    pub code: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::prepayment_items)]
+pub struct InsertPrepaymentItem {
+    pub prepayment_id: i32,
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub quantity: i32,
+}
+
+#[derive(Serialize)]
+pub struct PrepaymentLine {
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub name: Option<String>,
+    pub quantity: i32,
+}
+
+#[derive(Serialize)]
+pub struct FullPrepayment {
+    pub id: i32,
+    pub circle_id: i32,
+    pub buyer_name: String,
+    pub contact: Option<String>,
+    pub note: Option<String>,
+    pub user_id: Option<i32>,
+    pub order_id: Option<i32>,
+    pub code: String,
+    pub qr_payload: String,
+    pub created_at: SystemTime,
+    pub redeemed_at: Option<SystemTime>,
+    pub redeemed_by: Option<i32>,
+    pub items: Vec<PrepaymentLine>,
+}
+
+/// Accepts a code as typed, with any case, spacing or dashes, or a scanned
+/// QR payload.
+fn normalize_code(input: &str) -> String {
+    let input = input.trim().to_ascii_uppercase();
+    let code = input.strip_prefix(QR_PREFIX).unwrap_or(&input);
+
+    code.chars().filter(char::is_ascii_alphanumeric).collect()
+}
+
+/// Looks up a prepayment and checks that `user` may manage its circle.
+fn find_managed_prepayment(
+    user: &AuthenticatedUser,
+    prepayment_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Prepayment, CustomError> {
+    use crate::schema::prepayments;
+
+    let prepayment = prepayments::table
+        .find(prepayment_id)
+        .first::<Prepayment>(conn)
+        .map_err(handle_error)?;
+
+    user.check_permission(prepayment.circle_id)?;
+
+    Ok(prepayment)
+}
+
+fn find_managed_prepayment_by_code(
+    user: &AuthenticatedUser,
+    code: &str,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Prepayment, CustomError> {
+    use crate::schema::prepayments;
+
+    let prepayment = prepayments::table
+        .filter(prepayments::code.eq(normalize_code(code)))
+        .first::<Prepayment>(conn)
+        .map_err(handle_error)?;
+
+    user.check_permission(prepayment.circle_id)?;
+
+    Ok(prepayment)
+}
+
+/// Attaches the prepaid items to every prepayment, in a fixed number of
+/// queries.
+fn load_full_prepayments(
+    prepayments: Vec<Prepayment>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<FullPrepayment>, CustomError> {
+    use crate::schema::bundles;
+    use crate::schema::goods;
+    use crate::schema::prepayment_items;
+
+    let prepayment_ids = prepayments
+        .iter()
+        .map(|prepayment| prepayment.id)
+        .collect::<Vec<_>>();
+
+    let rows = prepayment_items::table
+        .left_join(goods::table)
+        .left_join(bundles::table)
+        .filter(prepayment_items::prepayment_id.eq_any(&prepayment_ids))
+        .order(prepayment_items::id)
+        .select((
+            prepayment_items::prepayment_id,
+            prepayment_items::goods_id,
+            prepayment_items::bundle_id,
+            goods::name.nullable(),
+            bundles::name.nullable(),
+            prepayment_items::quantity,
+        ))
+        .load::<(
+            i32,
+            Option<i32>,
+            Option<i32>,
+            Option<String>,
+            Option<String>,
+            i32,
+        )>(conn)
+        .map_err(handle_error)?;
+
+    let mut lines: HashMap<i32, Vec<PrepaymentLine>> = HashMap::new();
+
+    for (prepayment_id, goods_id, bundle_id, goods_name, bundle_name, quantity) in rows {
+        lines
+            .entry(prepayment_id)
+            .or_default()
+            .push(PrepaymentLine {
+                goods_id,
+                bundle_id,
+                name: goods_name.or(bundle_name),
+                quantity,
+            });
+    }
+
+    Ok(prepayments
+        .into_iter()
+        .map(|prepayment| FullPrepayment {
+            items: lines.remove(&prepayment.id).unwrap_or_default(),
+            qr_payload: format!("{}{}", QR_PREFIX, prepayment.code),
+            id: prepayment.id,
+            circle_id: prepayment.circle_id,
+            buyer_name: prepayment.buyer_name,
+            contact: prepayment.contact,
+            note: prepayment.note,
+            user_id: prepayment.user_id,
+            order_id: prepayment.order_id,
+            code: prepayment.code,
+            created_at: prepayment.created_at,
+            redeemed_at: prepayment.redeemed_at,
+            redeemed_by: prepayment.redeemed_by,
+        })
+        .collect())
+}
+
+fn load_full_prepayment(
+    prepayment: Prepayment,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<FullPrepayment, CustomError> {
+    load_full_prepayments(vec![prepayment], conn)?
+        .pop()
+        .ok_or_else(|| handle_error(diesel::result::Error::NotFound))
+}
+
+#[post(
+    "/circles/<circle_id>/prepayments",
+    format = "json",
+    data = "<new_prepayment>"
+)]
+pub fn post_prepayment(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    new_prepayment: Json<NewPrepayment>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<Json<FullPrepayment>>, CustomError> {
+    use crate::schema::prepayment_items;
+    use crate::schema::prepayments;
+
+    user.check_permission(circle_id)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    if new_prepayment.items.is_empty() {
+        return Err(Custom(
+            Status::BadRequest,
+            Json(ErrorInfo::new("Prepayment has no items".into())),
+        ));
+    }
+
+    for item in new_prepayment.items.iter() {
+        check_circle_item(circle_id, item.goods_id, item.bundle_id, &mut conn)?;
+
+        if item.quantity < 1 {
+            return Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new("Quantity must be at least 1".into())),
+            ));
+        }
+    }
+
+    let new_prepayment = new_prepayment.into_inner();
+
+    let prepayment = conn
+        .transaction(|conn| {
+            let prepayment = diesel::insert_into(prepayments::table)
+                .values(InsertPrepayment {
+                    circle_id,
+                    buyer_name: new_prepayment.buyer_name,
+                    contact: new_prepayment.contact,
+                    note: new_prepayment.note,
+                    user_id: None,
+                    order_id: None,
+                    code: generate_code(CODE_LENGTH),
+                })
+                .get_result::<Prepayment>(conn)?;
+
+            diesel::insert_into(prepayment_items::table)
+                .values(
+                    new_prepayment
+                        .items
+                        .iter()
+                        .map(|item| InsertPrepaymentItem {
+                            prepayment_id: prepayment.id,
+                            goods_id: item.goods_id,
+                            bundle_id: item.bundle_id,
+                            quantity: item.quantity,
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .execute(conn)?;
+
+            Ok(prepayment)
+        })
+        .map_err(handle_error)?;
+
+    let prepayment = load_full_prepayment(prepayment, &mut conn)?;
+
+    Ok(Created::new(format!("/prepayments/{}", prepayment.id)).body(Json(prepayment)))
+}
+
+/// Adds every order of an order form that is not in the ledger yet, with the
+/// buyer's nickname as the name. Running it again picks up only new orders.
+#[post("/order-forms/<order_form_id>/prepayments")]
+pub fn post_order_form_prepayments(
+    user: AuthenticatedUser,
+    order_form_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<Json<Vec<FullPrepayment>>>, CustomError> {
+    use crate::schema::order_form_items;
+    use crate::schema::order_items;
+    use crate::schema::orders;
+    use crate::schema::prepayment_items;
+    use crate::schema::prepayments;
+    use crate::schema::users;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let order_form = find_managed_order_form(&user, order_form_id, &mut conn)?;
+
+    let orders = orders::table
+        .inner_join(users::table)
+        .left_join(prepayments::table)
+        .filter(orders::order_form_id.eq(order_form_id))
+        .filter(prepayments::id.nullable().is_null())
+        .order(orders::id)
+        .select((orders::id, orders::user_id, orders::note, users::nickname))
+        .load::<(i32, i32, Option<String>, String)>(&mut conn)
+        .map_err(handle_error)?;
+
+    let order_ids = orders.iter().map(|(id, ..)| *id).collect::<Vec<_>>();
+
+    let mut items: HashMap<i32, Vec<NewPrepaymentItem>> = HashMap::new();
+
+    for (order_id, goods_id, bundle_id, quantity) in order_items::table
+        .inner_join(order_form_items::table)
+        .filter(order_items::order_id.eq_any(&order_ids))
+        .select((
+            order_items::order_id,
+            order_form_items::goods_id,
+            order_form_items::bundle_id,
+            order_items::quantity,
+        ))
+        .load::<(i32, Option<i32>, Option<i32>, i32)>(&mut conn)
+        .map_err(handle_error)?
+    {
+        items.entry(order_id).or_default().push(NewPrepaymentItem {
+            goods_id,
+            bundle_id,
+            quantity,
+        });
+    }
+
+    let created = conn
+        .transaction(|conn| {
+            let created = diesel::insert_into(prepayments::table)
+                .values(
+                    orders
+                        .into_iter()
+                        .map(|(order_id, user_id, note, nickname)| InsertPrepayment {
+                            circle_id: order_form.circle_id,
+                            buyer_name: nickname,
+                            contact: None,
+                            note,
+                            user_id: Some(user_id),
+                            order_id: Some(order_id),
+                            code: generate_code(CODE_LENGTH),
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .get_results::<Prepayment>(conn)?;
+
+            diesel::insert_into(prepayment_items::table)
+                .values(
+                    created
+                        .iter()
+                        .flat_map(|prepayment| {
+                            let order_id = prepayment.order_id.unwrap_or_default();
+
+                            items
+                                .remove(&order_id)
+                                .unwrap_or_default()
+                                .into_iter()
+                                .map(|item| InsertPrepaymentItem {
+                                    prepayment_id: prepayment.id,
+                                    goods_id: item.goods_id,
+                                    bundle_id: item.bundle_id,
+                                    quantity: item.quantity,
+                                })
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .execute(conn)?;
+
+            Ok(created)
+        })
+        .map_err(handle_error)?;
+
+    let created = load_full_prepayments(created, &mut conn)?;
+
+    Ok(Created::new(format!("/circles/{}/prepayments", order_form.circle_id)).body(Json(created)))
+}
+
+/// Lists the circle's ledger, optionally only the entries that are (or are
+/// not yet) picked up.
+#[get("/circles/<circle_id>/prepayments?<redeemed>")]
+pub fn get_circle_prepayments(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    redeemed: Option<bool>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Vec<FullPrepayment>>, CustomError> {
+    use crate::schema::prepayments;
+
+    user.check_permission(circle_id)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let mut query = prepayments::table
+        .filter(prepayments::circle_id.eq(circle_id))
+        .order(prepayments::id)
+        .into_boxed();
+
+    query = match redeemed {
+        Some(true) => query.filter(prepayments::redeemed_at.is_not_null()),
+        Some(false) => query.filter(prepayments::redeemed_at.is_null()),
+        None => query,
+    };
+
+    let prepayments = query.load::<Prepayment>(&mut conn).map_err(handle_error)?;
+
+    load_full_prepayments(prepayments, &mut conn).map(Json)
+}
+
+#[get("/prepayments/<prepayment_id>")]
+pub fn get_prepayment_by_id(
+    user: AuthenticatedUser,
+    prepayment_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<FullPrepayment>, CustomError> {
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let prepayment = find_managed_prepayment(&user, prepayment_id, &mut conn)?;
+
+    load_full_prepayment(prepayment, &mut conn).map(Json)
+}
+
+#[patch(
+    "/prepayments/<prepayment_id>",
+    format = "json",
+    data = "<update_prepayment>"
+)]
+pub fn patch_prepayment(
+    user: AuthenticatedUser,
+    prepayment_id: i32,
+    update_prepayment: Json<UpdatePrepayment>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<FullPrepayment>, CustomError> {
+    use crate::schema::prepayments;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_managed_prepayment(&user, prepayment_id, &mut conn)?;
+
+    let prepayment = diesel::update(prepayments::table.find(prepayment_id))
+        .set(update_prepayment.into_inner())
+        .get_result::<Prepayment>(&mut conn)
+        .map_err(handle_error)?;
+
+    load_full_prepayment(prepayment, &mut conn).map(Json)
+}
+
+#[delete("/prepayments/<prepayment_id>")]
+pub fn delete_prepayment(
+    user: AuthenticatedUser,
+    prepayment_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::prepayments;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_managed_prepayment(&user, prepayment_id, &mut conn)?;
+
+    let size = diesel::delete(prepayments::table.find(prepayment_id))
+        .execute(&mut conn)
+        .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// The buyer's own pickup codes, for showing at the booth.
+#[get("/users/me/prepayments")]
+pub fn get_my_prepayments(
+    user: AuthenticatedUser,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Vec<FullPrepayment>>, CustomError> {
+    use crate::schema::prepayments;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let prepayments = prepayments::table
+        .filter(prepayments::user_id.eq(user.id))
+        .order(prepayments::id)
+        .load::<Prepayment>(&mut conn)
+        .map_err(handle_error)?;
+
+    load_full_prepayments(prepayments, &mut conn).map(Json)
+}
+
+/// Looks up a pickup code, as typed or as scanned from the QR payload.
+#[get("/pickup-codes/<code>")]
+pub fn get_pickup_code(
+    user: AuthenticatedUser,
+    code: &str,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<FullPrepayment>, CustomError> {
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let prepayment = find_managed_prepayment_by_code(&user, code, &mut conn)?;
+
+    load_full_prepayment(prepayment, &mut conn).map(Json)
+}
+
+/// Marks a pickup code as handed out. A code can be redeemed only once.
+#[post("/pickup-codes/<code>/redeem")]
+pub fn redeem_pickup_code(
+    user: AuthenticatedUser,
+    code: &str,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<FullPrepayment>, CustomError> {
+    use crate::schema::prepayments;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let prepayment = find_managed_prepayment_by_code(&user, code, &mut conn)?;
+
+    // Filtering on `redeemed_at` keeps two devices scanning the same code at
+    // once from both succeeding
+    let redeemed = diesel::update(
+        prepayments::table
+            .find(prepayment.id)
+            .filter(prepayments::redeemed_at.is_null()),
+    )
+    .set((
+        prepayments::redeemed_at.eq(SystemTime::now()),
+        prepayments::redeemed_by.eq(user.id),
+    ))
+    .get_result::<Prepayment>(&mut conn)
+    .optional()
+    .map_err(handle_error)?
+    .ok_or_else(|| {
+        Custom(
+            Status::Conflict,
+            Json(ErrorInfo::new("Pickup code was already redeemed".into())),
+        )
+    })?;
+
+    load_full_prepayment(redeemed, &mut conn).map(Json)
+}
@@ -0,0 +1,409 @@
+use crate::error_handler::{handle_error, CustomError, ErrorInfo};
+use crate::models::{
+    AuthenticatedUser, MatchStatusEnum, Reconciliation, ReconciliationDeposit,
+    ReconciliationPayment,
+};
+use crate::utils::bank::{self, Match, Transfer};
+use crate::DbPool;
+
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use rocket::http::{ContentType, Status};
+use rocket::response::status::{Created, Custom};
+use rocket::serde::json::Json;
+use rocket::serde::Deserialize;
+use rocket::Data;
+use rocket_multipart_form_data::{
+    MultipartFormData, MultipartFormDataField, MultipartFormDataOptions,
+};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::time::SystemTime;
+
+/// Upper bound for each uploaded CSV file.
+const CSV_SIZE_LIMIT: u64 = 5_000_000;
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::reconciliation_payments)]
+pub struct InsertReconciliationPayment<'a> {
+    pub reconciliation_id: i32,
+    pub depositor_name: &'a str,
+    pub amount: i32,
+    pub note: Option<&'a str>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::reconciliation_deposits)]
+pub struct InsertReconciliationDeposit<'a> {
+    pub reconciliation_id: i32,
+    pub line: i32,
+    pub depositor_name: &'a str,
+    pub amount: i32,
+    pub deposited_at: Option<&'a str>,
+    pub status: MatchStatusEnum,
+    pub payment_id: Option<i32>,
+    pub candidate_ids: Vec<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct ResolveDeposit {
+    pub payment_id: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct PaymentStatus {
+    pub id: i32,
+    pub depositor_name: String,
+    pub amount: i32,
+    pub note: Option<String>,
+    pub deposit_id: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct FullReconciliation {
+    pub id: i32,
+    pub circle_id: i32,
+    pub title: String,
+    pub created_at: SystemTime,
+    pub payments: Vec<PaymentStatus>,
+    pub deposits: Vec<ReconciliationDeposit>,
+}
+
+/// Looks up a reconciliation and checks that `user` may manage its circle.
+fn find_managed_reconciliation(
+    user: &AuthenticatedUser,
+    reconciliation_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Reconciliation, CustomError> {
+    use crate::schema::reconciliations;
+
+    let reconciliation = reconciliations::table
+        .find(reconciliation_id)
+        .first::<Reconciliation>(conn)
+        .map_err(handle_error)?;
+
+    user.check_permission(reconciliation.circle_id)?;
+
+    Ok(reconciliation)
+}
+
+fn load_full_reconciliation(
+    reconciliation: Reconciliation,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<FullReconciliation, CustomError> {
+    use crate::schema::reconciliation_deposits;
+    use crate::schema::reconciliation_payments;
+
+    let payments = reconciliation_payments::table
+        .left_join(reconciliation_deposits::table)
+        .filter(reconciliation_payments::reconciliation_id.eq(reconciliation.id))
+        .order(reconciliation_payments::id)
+        .select((
+            reconciliation_payments::all_columns,
+            reconciliation_deposits::id.nullable(),
+        ))
+        .load::<(ReconciliationPayment, Option<i32>)>(conn)
+        .map_err(handle_error)?
+        .into_iter()
+        .map(|(payment, deposit_id)| PaymentStatus {
+            id: payment.id,
+            depositor_name: payment.depositor_name,
+            amount: payment.amount,
+            note: payment.note,
+            deposit_id,
+        })
+        .collect();
+
+    let deposits = reconciliation_deposits::table
+        .filter(reconciliation_deposits::reconciliation_id.eq(reconciliation.id))
+        .order(reconciliation_deposits::line)
+        .load::<ReconciliationDeposit>(conn)
+        .map_err(handle_error)?;
+
+    Ok(FullReconciliation {
+        id: reconciliation.id,
+        circle_id: reconciliation.circle_id,
+        title: reconciliation.title,
+        created_at: reconciliation.created_at,
+        payments,
+        deposits,
+    })
+}
+
+fn read_transfers(form: &MultipartFormData, field: &str) -> Result<Vec<Transfer>, CustomError> {
+    let raw = form
+        .raw
+        .get(field)
+        .and_then(|fields| fields.first())
+        .ok_or_else(|| {
+            Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new(format!("{} field not found", field))),
+            )
+        })?;
+
+    // Korean banks still export statements in CP949 (EUC-KR), so fall back
+    // to it for files that are not UTF-8.
+    let text = match std::str::from_utf8(&raw.raw) {
+        Ok(text) => Cow::Borrowed(text),
+        Err(_) => encoding_rs::EUC_KR
+            .decode_without_bom_handling_and_without_replacement(&raw.raw)
+            .ok_or_else(|| {
+                Custom(
+                    Status::UnprocessableEntity,
+                    Json(ErrorInfo::new(format!(
+                        "{} must be a UTF-8 or CP949 encoded CSV file",
+                        field
+                    ))),
+                )
+            })?,
+    };
+
+    bank::parse_transfers(&text).map_err(|message| {
+        Custom(
+            Status::UnprocessableEntity,
+            Json(ErrorInfo::new(format!("{}: {}", field, message))),
+        )
+    })
+}
+
+/// Takes a multipart form with a `title`, the circle's expected `payments`
+/// and the bank `statement`, both as CSV, and stores how the statement's
+/// deposits match up with the payments.
+#[post("/circles/<circle_id>/reconciliations", data = "<data>")]
+pub async fn post_reconciliation(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    content_type: &ContentType,
+    data: Data<'_>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<Json<FullReconciliation>>, CustomError> {
+    use crate::schema::reconciliation_deposits;
+    use crate::schema::reconciliation_payments;
+    use crate::schema::reconciliations;
+
+    user.check_permission(circle_id)?;
+
+    let options = MultipartFormDataOptions::with_multipart_form_data_fields(vec![
+        MultipartFormDataField::text("title"),
+        MultipartFormDataField::raw("payments").size_limit(CSV_SIZE_LIMIT),
+        MultipartFormDataField::raw("statement").size_limit(CSV_SIZE_LIMIT),
+    ]);
+
+    let form = MultipartFormData::parse(content_type, data, options)
+        .await
+        .map_err(|_| {
+            Custom(
+                Status::UnprocessableEntity,
+                Json(ErrorInfo::new("cannot parse multipart data".into())),
+            )
+        })?;
+
+    let title = form
+        .texts
+        .get("title")
+        .and_then(|fields| fields.first())
+        .map(|field| field.text.trim().to_string())
+        .filter(|title| !title.is_empty())
+        .ok_or_else(|| {
+            Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new("title field not found".into())),
+            )
+        })?;
+
+    let payments = read_transfers(&form, "payments")?;
+    let deposits = read_transfers(&form, "statement")?;
+    let matches = bank::reconcile(&payments, &deposits);
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let reconciliation = conn
+        .transaction(|conn| {
+            let reconciliation = diesel::insert_into(reconciliations::table)
+                .values((
+                    reconciliations::circle_id.eq(circle_id),
+                    reconciliations::title.eq(&title),
+                ))
+                .get_result::<Reconciliation>(conn)?;
+
+            let payment_ids = diesel::insert_into(reconciliation_payments::table)
+                .values(
+                    payments
+                        .iter()
+                        .map(|payment| InsertReconciliationPayment {
+                            reconciliation_id: reconciliation.id,
+                            depositor_name: &payment.depositor_name,
+                            amount: payment.amount,
+                            note: payment.note.as_deref(),
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .returning(reconciliation_payments::id)
+                .get_results::<i32>(conn)?;
+
+            diesel::insert_into(reconciliation_deposits::table)
+                .values(
+                    deposits
+                        .iter()
+                        .zip(matches)
+                        .map(|(deposit, result)| {
+                            let (status, payment_id, candidate_ids) = match result {
+                                Match::Exact(i) => {
+                                    (MatchStatusEnum::matched, Some(payment_ids[i]), vec![])
+                                }
+                                Match::Fuzzy(i) => {
+                                    (MatchStatusEnum::fuzzy, Some(payment_ids[i]), vec![])
+                                }
+                                Match::Ambiguous(candidates) => (
+                                    MatchStatusEnum::ambiguous,
+                                    None,
+                                    candidates.into_iter().map(|i| payment_ids[i]).collect(),
+                                ),
+                                Match::Unmatched => (MatchStatusEnum::unmatched, None, vec![]),
+                            };
+
+                            InsertReconciliationDeposit {
+                                reconciliation_id: reconciliation.id,
+                                line: deposit.line,
+                                depositor_name: &deposit.depositor_name,
+                                amount: deposit.amount,
+                                deposited_at: deposit.deposited_at.as_deref(),
+                                status,
+                                payment_id,
+                                candidate_ids,
+                            }
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .execute(conn)?;
+
+            Ok(reconciliation)
+        })
+        .map_err(handle_error)?;
+
+    let full_reconciliation = load_full_reconciliation(reconciliation, &mut conn)?;
+
+    Ok(
+        Created::new(format!("/reconciliations/{}", full_reconciliation.id))
+            .body(Json(full_reconciliation)),
+    )
+}
+
+#[get("/circles/<circle_id>/reconciliations")]
+pub fn get_circle_reconciliations(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Vec<Reconciliation>>, CustomError> {
+    use crate::schema::reconciliations;
+
+    user.check_permission(circle_id)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    reconciliations::table
+        .filter(reconciliations::circle_id.eq(circle_id))
+        .order(reconciliations::created_at.desc())
+        .load::<Reconciliation>(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
+
+#[get("/reconciliations/<reconciliation_id>")]
+pub fn get_reconciliation_by_id(
+    user: AuthenticatedUser,
+    reconciliation_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<FullReconciliation>, CustomError> {
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let reconciliation = find_managed_reconciliation(&user, reconciliation_id, &mut conn)?;
+
+    load_full_reconciliation(reconciliation, &mut conn).map(Json)
+}
+
+#[delete("/reconciliations/<reconciliation_id>")]
+pub fn delete_reconciliation(
+    user: AuthenticatedUser,
+    reconciliation_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::reconciliations;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_managed_reconciliation(&user, reconciliation_id, &mut conn)?;
+
+    let size = diesel::delete(reconciliations::table.find(reconciliation_id))
+        .execute(&mut conn)
+        .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Settles a deposit by hand: assigns it to `payment_id`, or marks it
+/// unmatched when that is null. A payment already taken by another deposit
+/// is a conflict.
+#[patch(
+    "/reconciliations/<reconciliation_id>/deposits/<deposit_id>",
+    format = "json",
+    data = "<resolve_deposit>"
+)]
+pub fn patch_reconciliation_deposit(
+    user: AuthenticatedUser,
+    reconciliation_id: i32,
+    deposit_id: i32,
+    resolve_deposit: Json<ResolveDeposit>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<ReconciliationDeposit>, CustomError> {
+    use crate::schema::reconciliation_deposits;
+    use crate::schema::reconciliation_payments;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_managed_reconciliation(&user, reconciliation_id, &mut conn)?;
+
+    let status = match resolve_deposit.payment_id {
+        Some(payment_id) => {
+            let size = reconciliation_payments::table
+                .find(payment_id)
+                .filter(reconciliation_payments::reconciliation_id.eq(reconciliation_id))
+                .count()
+                .get_result::<i64>(&mut conn)
+                .map_err(handle_error)?;
+
+            if size == 0 {
+                return Err(Custom(
+                    Status::BadRequest,
+                    Json(ErrorInfo::new(
+                        "Payment does not belong to this reconciliation".into(),
+                    )),
+                ));
+            }
+
+            MatchStatusEnum::resolved
+        }
+        None => MatchStatusEnum::unmatched,
+    };
+
+    diesel::update(
+        reconciliation_deposits::table
+            .find(deposit_id)
+            .filter(reconciliation_deposits::reconciliation_id.eq(reconciliation_id)),
+    )
+    .set((
+        reconciliation_deposits::payment_id.eq(resolve_deposit.payment_id),
+        reconciliation_deposits::status.eq(status),
+    ))
+    .get_result::<ReconciliationDeposit>(&mut conn)
+    .map(Json)
+    .map_err(handle_error)
+}
@@ -1,5 +1,6 @@
 use crate::error_handler::{handle_error, CustomError, ErrorInfo};
 use crate::models::{AuthenticatedUser, Ref};
+use crate::utils::search::name_matches;
 use crate::DbPool;
 use diesel::prelude::*;
 use rocket::http::Status;
@@ -49,8 +50,13 @@ pub fn get_references(
 
     let mut conn = pool.get().expect("Failed to get database connection");
 
-    refs::table
-        .filter(refs::name.similar_to(format!("%{}%", name.unwrap_or("".into()))))
+    let mut query = refs::table.into_boxed();
+
+    if let Some(name) = name {
+        query = query.filter(name_matches(refs::name.nullable(), &name));
+    }
+
+    query
         .load::<Ref>(&mut conn)
         .map(Json)
         .map_err(handle_error)
@@ -0,0 +1,575 @@
+use std::collections::{HashMap, HashSet};
+use std::time::SystemTime;
+
+use crate::error_handler::{handle_error, CustomError, ErrorInfo};
+use crate::models::{AuthenticatedUser, Sale};
+use crate::routes::goods::StockChange;
+use crate::routes::orders::check_circle_item;
+use crate::routes::stream::{ChangeAction, ChangeFeed, ChangeKind};
+use crate::utils::pagination::{Page, PageRequest, Sort};
+use crate::DbPool;
+
+use diesel::dsl::{count_star, sql};
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use diesel::sql_types::{BigInt, Nullable};
+use rocket::http::Status;
+use rocket::response::status::{Created, Custom};
+use rocket::serde::json::Json;
+use rocket::serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Deserialize)]
+pub struct NewSale {
+    pub items: Vec<NewSaleItem>,
+}
+
+#[derive(Deserialize)]
+pub struct NewSaleItem {
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub quantity: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::sales)]
+pub struct InsertSale {
+    pub circle_id: i32,
+    pub user_id: Option<i32>,
+    pub total: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::sale_items)]
+pub struct InsertSaleItem {
+    pub sale_id: i32,
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub quantity: i32,
+    pub price: i32,
+}
+
+#[derive(Serialize)]
+pub struct SaleLine {
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub name: Option<String>,
+    pub quantity: i32,
+    pub price: i32,
+    pub subtotal: i64,
+}
+
+#[derive(Serialize)]
+pub struct FullSale {
+    pub id: i32,
+    pub circle_id: i32,
+    pub user_id: Option<i32>,
+    pub total: i32,
+    pub created_at: SystemTime,
+    pub items: Vec<SaleLine>,
+}
+
+#[derive(Serialize)]
+pub struct SoldItem {
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub name: Option<String>,
+    pub quantity: i64,
+    pub amount: i64,
+}
+
+#[derive(Serialize)]
+pub struct SalesSummary {
+    pub sales: i64,
+    pub total: i64,
+    pub items: Vec<SoldItem>,
+}
+
+/// Attaches the sold items to every sale, in a fixed number of queries.
+fn load_full_sales(
+    sales: Vec<Sale>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<FullSale>, CustomError> {
+    use crate::schema::bundles;
+    use crate::schema::goods;
+    use crate::schema::sale_items;
+
+    let sale_ids = sales.iter().map(|sale| sale.id).collect::<Vec<_>>();
+
+    let rows = sale_items::table
+        .left_join(goods::table)
+        .left_join(bundles::table)
+        .filter(sale_items::sale_id.eq_any(&sale_ids))
+        .order(sale_items::id)
+        .select((
+            sale_items::sale_id,
+            sale_items::goods_id,
+            sale_items::bundle_id,
+            goods::name.nullable(),
+            bundles::name.nullable(),
+            sale_items::quantity,
+            sale_items::price,
+        ))
+        .load::<(
+            i32,
+            Option<i32>,
+            Option<i32>,
+            Option<String>,
+            Option<String>,
+            i32,
+            i32,
+        )>(conn)
+        .map_err(handle_error)?;
+
+    let mut lines: HashMap<i32, Vec<SaleLine>> = HashMap::new();
+
+    for (sale_id, goods_id, bundle_id, goods_name, bundle_name, quantity, price) in rows {
+        lines.entry(sale_id).or_default().push(SaleLine {
+            goods_id,
+            bundle_id,
+            name: goods_name.or(bundle_name),
+            quantity,
+            price,
+            subtotal: i64::from(quantity) * i64::from(price),
+        });
+    }
+
+    Ok(sales
+        .into_iter()
+        .map(|sale| FullSale {
+            items: lines.remove(&sale.id).unwrap_or_default(),
+            id: sale.id,
+            circle_id: sale.circle_id,
+            user_id: sale.user_id,
+            total: sale.total,
+            created_at: sale.created_at,
+        })
+        .collect())
+}
+
+enum StockError {
+    Query(diesel::result::Error),
+    /// The item at this index has less stock left than is being sold.
+    Short(usize),
+}
+
+impl From<diesel::result::Error> for StockError {
+    fn from(error: diesel::result::Error) -> Self {
+        StockError::Query(error)
+    }
+}
+
+impl StockError {
+    /// `names` are the names of the items passed to [`adjust_stock`].
+    fn into_response(self, names: &[String]) -> CustomError {
+        match self {
+            StockError::Query(error) => handle_error(error),
+            StockError::Short(index) => Custom(
+                Status::Conflict,
+                Json(ErrorInfo::new(format!(
+                    "Not enough stock for {}",
+                    names[index]
+                ))),
+            ),
+        }
+    }
+}
+
+/// Takes sold items off the stock of whichever goods and bundles keep count,
+/// or puts them back when `restock` is set. Items that run out are marked
+/// sold out, and sold out items that get stock back become available again.
+///
+/// Stock is only taken off while enough is left, so two devices selling the
+/// last unit at once cannot both succeed; the one that comes second gets
+/// [`StockError::Short`] and has to roll back.
+///
+/// Returns the items whose stock changed.
+fn adjust_stock(
+    items: &[NewSaleItem],
+    restock: bool,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<(ChangeKind, i32)>, StockError> {
+    use crate::schema::bundles;
+    use crate::schema::goods;
+
+    let mut goods_ids = Vec::new();
+    let mut bundle_ids = Vec::new();
+
+    for (index, item) in items.iter().enumerate() {
+        let (delta, needed) = if restock {
+            (item.quantity, 0)
+        } else {
+            (-item.quantity, item.quantity)
+        };
+
+        // Untracked stock stays NULL, as `NULL + delta` is NULL.
+        if let Some(goods_id) = item.goods_id {
+            let (id, stock) = diesel::update(
+                goods::table
+                    .find(goods_id)
+                    .filter(goods::stock.is_null().or(goods::stock.ge(needed))),
+            )
+            .set((
+                goods::stock.eq(goods::stock + delta),
+                goods::availability.eq(StockChange::By(delta).availability()),
+            ))
+            .returning((goods::id, goods::stock))
+            .get_result::<(i32, Option<i32>)>(conn)
+            .optional()?
+            .ok_or(StockError::Short(index))?;
+
+            if stock.is_some() {
+                goods_ids.push(id);
+            }
+        }
+
+        if let Some(bundle_id) = item.bundle_id {
+            let (id, stock) = diesel::update(
+                bundles::table
+                    .find(bundle_id)
+                    .filter(bundles::stock.is_null().or(bundles::stock.ge(needed))),
+            )
+            .set((
+                bundles::stock.eq(bundles::stock + delta),
+                bundles::availability.eq(StockChange::By(delta).availability()),
+            ))
+            .returning((bundles::id, bundles::stock))
+            .get_result::<(i32, Option<i32>)>(conn)
+            .optional()?
+            .ok_or(StockError::Short(index))?;
+
+            if stock.is_some() {
+                bundle_ids.push(id);
+            }
+        }
+    }
+
+    Ok(goods_ids
+        .into_iter()
+        .map(|id| (ChangeKind::goods, id))
+        .chain(bundle_ids.into_iter().map(|id| (ChangeKind::bundle, id)))
+        .collect())
+}
+
+/// Records a sale at the booth at the catalog prices, taking the sold items
+/// off the stock. Selling a bundle only counts down the bundle's own stock,
+/// since which goods went into it is not known.
+#[post("/circles/<circle_id>/sales", format = "json", data = "<new_sale>")]
+pub fn post_sale(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    new_sale: Json<NewSale>,
+    feed: &rocket::State<ChangeFeed>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<Json<FullSale>>, CustomError> {
+    use crate::schema::bundles;
+    use crate::schema::goods;
+    use crate::schema::sale_items;
+    use crate::schema::sales;
+
+    user.check_permission(circle_id)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    if new_sale.items.is_empty() {
+        return Err(Custom(
+            Status::BadRequest,
+            Json(ErrorInfo::new("Sale has no items".into())),
+        ));
+    }
+
+    let mut seen = HashSet::new();
+    let mut names = Vec::with_capacity(new_sale.items.len());
+    let mut prices = Vec::with_capacity(new_sale.items.len());
+
+    for item in new_sale.items.iter() {
+        check_circle_item(circle_id, item.goods_id, item.bundle_id, &mut conn)?;
+
+        if !seen.insert((item.goods_id, item.bundle_id)) {
+            return Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new("Item is listed twice".into())),
+            ));
+        }
+
+        if item.quantity < 1 {
+            return Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new("Quantity must be at least 1".into())),
+            ));
+        }
+
+        let (name, price, stock) = match (item.goods_id, item.bundle_id) {
+            (Some(goods_id), _) => goods::table
+                .find(goods_id)
+                .select((goods::name, goods::price, goods::stock))
+                .first::<(Option<String>, Option<i32>, Option<i32>)>(&mut conn),
+            (_, Some(bundle_id)) => bundles::table
+                .find(bundle_id)
+                .select((bundles::name, bundles::price, bundles::stock))
+                .first::<(Option<String>, Option<i32>, Option<i32>)>(&mut conn),
+            _ => Err(diesel::result::Error::NotFound),
+        }
+        .map_err(handle_error)?;
+
+        let name = name.unwrap_or_default();
+
+        let Some(price) = price else {
+            return Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new(format!("{} has no price", name))),
+            ));
+        };
+
+        if stock.is_some_and(|stock| stock < item.quantity) {
+            return Err(Custom(
+                Status::Conflict,
+                Json(ErrorInfo::new(format!("Not enough stock for {}", name))),
+            ));
+        }
+
+        names.push(name);
+        prices.push(price);
+    }
+
+    let items = new_sale.into_inner().items;
+    let total = items
+        .iter()
+        .zip(&prices)
+        .map(|(item, price)| i64::from(item.quantity) * i64::from(*price))
+        .sum::<i64>();
+    let total = i32::try_from(total).map_err(|_| {
+        Custom(
+            Status::BadRequest,
+            Json(ErrorInfo::new("Sale total is too large".into())),
+        )
+    })?;
+
+    let (sale, changed) = conn
+        .transaction(|conn| {
+            let sale = diesel::insert_into(sales::table)
+                .values(InsertSale {
+                    circle_id,
+                    user_id: Some(user.id),
+                    total,
+                })
+                .get_result::<Sale>(conn)?;
+
+            diesel::insert_into(sale_items::table)
+                .values(
+                    items
+                        .iter()
+                        .zip(&prices)
+                        .map(|(item, price)| InsertSaleItem {
+                            sale_id: sale.id,
+                            goods_id: item.goods_id,
+                            bundle_id: item.bundle_id,
+                            quantity: item.quantity,
+                            price: *price,
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .execute(conn)?;
+
+            let changed = adjust_stock(&items, false, conn)?;
+
+            Ok((sale, changed))
+        })
+        .map_err(|error: StockError| error.into_response(&names))?;
+
+    for (kind, id) in changed {
+        feed.publish(kind, ChangeAction::updated, id, circle_id);
+    }
+
+    let sale = load_full_sales(vec![sale], &mut conn)?
+        .pop()
+        .ok_or_else(|| handle_error(diesel::result::Error::NotFound))?;
+
+    Ok(Created::new(format!("/circles/{}/sales", circle_id)).body(Json(sale)))
+}
+
+#[get("/circles/<circle_id>/sales?<limit>&<cursor>")]
+pub fn get_circle_sales(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    limit: Option<i64>,
+    cursor: Option<String>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Page<FullSale>>, CustomError> {
+    use crate::schema::sales;
+
+    user.check_permission(circle_id)?;
+
+    let sort = Sort::new(
+        Some("-created_at".into()),
+        "sales.id",
+        &[("created_at", &["sales.created_at"])],
+    )?;
+    let page_request = PageRequest::new(limit, cursor.as_deref(), &sort)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let total = sales::table
+        .filter(sales::circle_id.eq(circle_id))
+        .count()
+        .get_result::<i64>(&mut conn)
+        .map_err(handle_error)?;
+
+    let mut query = sales::table
+        .filter(sales::circle_id.eq(circle_id))
+        .order((sales::created_at.desc(), sales::id.desc()))
+        .into_boxed();
+
+    if let Some(after) = page_request.after() {
+        query = query.filter(after);
+    }
+
+    let sales = query
+        .limit(page_request.fetch_limit())
+        .load::<Sale>(&mut conn)
+        .map_err(handle_error)?;
+
+    let page = page_request.page(sales, total, |sale| {
+        (vec![Some(sale.created_at.into())], sale.id)
+    });
+
+    page.try_map(|sales| load_full_sales(sales, &mut conn)).map(Json)
+}
+
+/// Undoes the most recent sale the user recorded for the circle, putting its
+/// items back in stock. Sales recorded by other members are left alone, so
+/// two people at the same booth cannot undo each other's sales.
+#[delete("/circles/<circle_id>/sales/last")]
+pub fn delete_last_sale(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    feed: &rocket::State<ChangeFeed>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<FullSale>, CustomError> {
+    use crate::schema::sales;
+
+    user.check_permission(circle_id)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let sale = sales::table
+        .filter(sales::circle_id.eq(circle_id))
+        .filter(sales::user_id.eq(user.id))
+        .order((sales::created_at.desc(), sales::id.desc()))
+        .first::<Sale>(&mut conn)
+        .map_err(handle_error)?;
+
+    let sale = load_full_sales(vec![sale], &mut conn)?
+        .pop()
+        .ok_or_else(|| handle_error(diesel::result::Error::NotFound))?;
+
+    let items = sale
+        .items
+        .iter()
+        .map(|line| NewSaleItem {
+            goods_id: line.goods_id,
+            bundle_id: line.bundle_id,
+            quantity: line.quantity,
+        })
+        .collect::<Vec<_>>();
+
+    let changed = conn
+        .transaction(|conn| {
+            // A second undo that read the same sale finds it already gone,
+            // and must not put its stock back again.
+            if diesel::delete(sales::table.find(sale.id)).execute(conn)? == 0 {
+                return Err(StockError::Query(diesel::result::Error::NotFound));
+            }
+
+            adjust_stock(&items, true, conn)
+        })
+        .map_err(|error| {
+            let names = sale
+                .items
+                .iter()
+                .map(|line| line.name.clone().unwrap_or_default())
+                .collect::<Vec<_>>();
+
+            error.into_response(&names)
+        })?;
+
+    for (kind, id) in changed {
+        feed.publish(kind, ChangeAction::updated, id, circle_id);
+    }
+
+    Ok(Json(sale))
+}
+
+/// Running totals of the circle's booth sales, overall and per item.
+#[get("/circles/<circle_id>/sales/summary")]
+pub fn get_sales_summary(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<SalesSummary>, CustomError> {
+    use crate::schema::bundles;
+    use crate::schema::goods;
+    use crate::schema::sale_items;
+    use crate::schema::sales;
+
+    user.check_permission(circle_id)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let (count, total) = sales::table
+        .filter(sales::circle_id.eq(circle_id))
+        .select((count_star(), diesel::dsl::sum(sales::total)))
+        .first::<(i64, Option<i64>)>(&mut conn)
+        .map_err(handle_error)?;
+
+    let totals = sale_items::table
+        .inner_join(sales::table)
+        .filter(sales::circle_id.eq(circle_id))
+        .group_by((sale_items::goods_id, sale_items::bundle_id))
+        .order((sale_items::goods_id, sale_items::bundle_id))
+        .select((
+            sale_items::goods_id,
+            sale_items::bundle_id,
+            diesel::dsl::sum(sale_items::quantity),
+            sql::<Nullable<BigInt>>("sum(sale_items.quantity::bigint * sale_items.price)"),
+        ))
+        .load::<(Option<i32>, Option<i32>, Option<i64>, Option<i64>)>(&mut conn)
+        .map_err(handle_error)?;
+
+    let goods_names = goods::table
+        .filter(goods::id.eq_any(totals.iter().filter_map(|(goods_id, ..)| *goods_id)))
+        .select((goods::id, goods::name))
+        .load::<(i32, Option<String>)>(&mut conn)
+        .map_err(handle_error)?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+    let bundle_names = bundles::table
+        .filter(bundles::id.eq_any(totals.iter().filter_map(|(_, bundle_id, ..)| *bundle_id)))
+        .select((bundles::id, bundles::name))
+        .load::<(i32, Option<String>)>(&mut conn)
+        .map_err(handle_error)?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+    let items = totals
+        .into_iter()
+        .map(|(goods_id, bundle_id, quantity, amount)| SoldItem {
+            name: match (goods_id, bundle_id) {
+                (Some(goods_id), _) => goods_names.get(&goods_id).cloned().flatten(),
+                (_, Some(bundle_id)) => bundle_names.get(&bundle_id).cloned().flatten(),
+                _ => None,
+            },
+            goods_id,
+            bundle_id,
+            quantity: quantity.unwrap_or(0),
+            amount: amount.unwrap_or(0),
+        })
+        .collect();
+
+    Ok(Json(SalesSummary {
+        sales: count,
+        total: total.unwrap_or(0),
+        items,
+    }))
+}
@@ -0,0 +1,153 @@
+use crate::error_handler::{handle_error, CustomError, ErrorInfo};
+use crate::utils::search::contains_pattern;
+use crate::DbPool;
+
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Float, Integer, Text};
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::Json;
+use serde::Serialize;
+
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 100;
+
+/// Searchable entities: (kind, table, title expression, body expression).
+const SOURCES: [(&str, &str, &str, &str); 5] = [
+    (
+        "circle",
+        "circles",
+        "coalesce(name, '')",
+        "coalesce(name, '') || ' ' || coalesce(description, '')",
+    ),
+    ("artist", "artists", "name", "name"),
+    (
+        "goods",
+        "goods",
+        "coalesce(name, '')",
+        "coalesce(name, '') || ' ' || coalesce(description, '')",
+    ),
+    ("character", "characters", "name", "name"),
+    ("reference", "refs", "name", "name"),
+];
+
+#[derive(QueryableByName, Serialize)]
+pub struct SearchHit {
+    #[diesel(sql_type = Text)]
+    pub kind: String,
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+    #[diesel(sql_type = Text)]
+    pub title: String,
+    #[diesel(sql_type = Float)]
+    pub score: f32,
+    #[diesel(sql_type = Text)]
+    pub snippet: String,
+}
+
+fn escape_html_sql(expr: &str) -> String {
+    format!(
+        "replace(replace(replace({}, '&', '&amp;'), '<', '&lt;'), '>', '&gt;')",
+        expr
+    )
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Escapes everything but letters and digits so PostgreSQL's regex engine
+/// treats `s` literally.
+fn escape_regex(s: &str) -> String {
+    s.chars().fold(String::with_capacity(s.len()), |mut acc, c| {
+        if !c.is_alphanumeric() && !c.is_whitespace() {
+            acc.push('\\');
+        }
+        acc.push(c);
+        acc
+    })
+}
+
+/// Builds the `SELECT` for one entity. Binds: `$1` the raw query, `$2` an
+/// escaped `ILIKE` pattern and `$3` an escaped regex for highlighting.
+fn source_query(kind: &str, table: &str, title: &str, body: &str) -> String {
+    let document = format!("to_tsvector('simple', {})", body);
+    let tsquery = "plainto_tsquery('simple', $1)";
+
+    format!(
+        "SELECT '{kind}' AS kind, id, {title} AS title, \
+            GREATEST( \
+                similarity({title}, $1), \
+                ts_rank({document}, {tsquery}), \
+                CASE WHEN {title} ILIKE $2 OR search_key({title}) LIKE search_key($2) \
+                    THEN 0.5 ELSE 0 END \
+            )::real AS score, \
+            CASE WHEN {document} @@ {tsquery} \
+                THEN ts_headline('simple', {escaped}, {tsquery}, \
+                    'StartSel=<mark>, StopSel=</mark>, MinWords=8, MaxWords=24') \
+                ELSE regexp_replace(left({escaped}, 200), $3, '<mark>\\&</mark>', 'gi') \
+            END AS snippet \
+        FROM {table} \
+        WHERE {title} % $1 OR {title} ILIKE $2 OR search_key({title}) LIKE search_key($2) \
+            OR {document} @@ {tsquery}",
+        escaped = escape_html_sql(body),
+    )
+}
+
+#[get("/search?<q>&<types>&<limit>")]
+pub fn search(
+    q: String,
+    types: Option<String>,
+    limit: Option<i64>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Vec<SearchHit>>, CustomError> {
+    let q = q.trim().to_string();
+
+    if q.is_empty() {
+        return Err(Custom(
+            Status::BadRequest,
+            Json(ErrorInfo::new("Query too short".into())),
+        ));
+    }
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let types = types
+        .as_deref()
+        .map(|types| types.split(',').map(str::trim).collect::<Vec<_>>());
+
+    if let Some(unknown) = types
+        .iter()
+        .flatten()
+        .find(|kind| !SOURCES.iter().any(|(source, ..)| source == *kind))
+    {
+        return Err(Custom(
+            Status::BadRequest,
+            Json(ErrorInfo::new(format!("Unknown search type {}", unknown))),
+        ));
+    }
+
+    let sources = SOURCES
+        .iter()
+        .filter(|(kind, ..)| types.as_ref().is_none_or(|types| types.contains(kind)))
+        .map(|(kind, table, title, body)| source_query(kind, table, title, body))
+        .collect::<Vec<_>>();
+
+    let sql = format!(
+        "SELECT * FROM ({}) AS hits ORDER BY score DESC, kind, id LIMIT $4",
+        sources.join(" UNION ALL ")
+    );
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    diesel::sql_query(sql)
+        .bind::<Text, _>(&q)
+        .bind::<Text, _>(contains_pattern(&q))
+        .bind::<Text, _>(escape_regex(&escape_html(&q)))
+        .bind::<BigInt, _>(limit)
+        .load::<SearchHit>(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
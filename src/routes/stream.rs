@@ -0,0 +1,110 @@
+use rocket::response::stream::{Event, EventStream};
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::{self, error::RecvError};
+use rocket::Shutdown;
+use serde::Serialize;
+
+/// How many changes a slow subscriber may fall behind before changes are
+/// dropped and it is told to resync.
+const CHANNEL_CAPACITY: usize = 256;
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum ChangeKind {
+    goods,
+    bundle,
+    link,
+    circle,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, Serialize)]
+pub enum ChangeAction {
+    created,
+    updated,
+    deleted,
+}
+
+/// A mutation of the catalog, as pushed to `/stream` subscribers.
+#[derive(Clone, Debug, Serialize)]
+pub struct CatalogChange {
+    pub kind: ChangeKind,
+    pub action: ChangeAction,
+    pub id: i32,
+    pub circle_id: i32,
+}
+
+impl CatalogChange {
+    fn matches(&self, circle_id: Option<i32>, goods_ids: &[i32]) -> bool {
+        if circle_id.is_some_and(|circle_id| circle_id != self.circle_id) {
+            return false;
+        }
+
+        goods_ids.is_empty() || (self.kind == ChangeKind::goods && goods_ids.contains(&self.id))
+    }
+}
+
+/// Sent as a `resync` event when a subscriber fell too far behind and missed
+/// `skipped` changes.
+#[derive(Serialize)]
+struct Resync {
+    skipped: u64,
+}
+
+/// Fans catalog changes out to every open `/stream`. Managed by Rocket and
+/// handed to the handlers that mutate goods, bundles, links and circles.
+pub struct ChangeFeed {
+    sender: broadcast::Sender<CatalogChange>,
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+
+        ChangeFeed { sender }
+    }
+}
+
+impl ChangeFeed {
+    pub fn publish(&self, kind: ChangeKind, action: ChangeAction, id: i32, circle_id: i32) {
+        // Sending only fails when nobody is listening, which is fine
+        let _ = self.sender.send(CatalogChange {
+            kind,
+            action,
+            id,
+            circle_id,
+        });
+    }
+}
+
+#[get("/stream?<circle_id>&<goods_id>")]
+pub fn stream(
+    circle_id: Option<i32>,
+    goods_id: Vec<i32>,
+    feed: &rocket::State<ChangeFeed>,
+    mut shutdown: Shutdown,
+) -> EventStream![] {
+    let mut receiver = feed.sender.subscribe();
+
+    EventStream! {
+        loop {
+            let change = select! {
+                change = receiver.recv() => match change {
+                    Ok(change) => change,
+                    Err(RecvError::Closed) => break,
+                    Err(RecvError::Lagged(skipped)) => {
+                        // Changes were dropped, so the client's copy of the
+                        // catalog may be stale and has to be fetched again.
+                        yield Event::json(&Resync { skipped }).event("resync");
+                        continue;
+                    }
+                },
+                _ = &mut shutdown => break,
+            };
+
+            if change.matches(circle_id, &goods_id) {
+                yield Event::json(&change);
+            }
+        }
+    }
+}
@@ -0,0 +1,541 @@
+use std::collections::{HashMap, HashSet};
+use std::time::SystemTime;
+
+use crate::error_handler::{handle_error, CustomError, ErrorInfo};
+use crate::models::{AuthenticatedUser, Survey, SurveyItem};
+use crate::routes::orders::check_circle_item;
+use crate::utils::csv;
+use crate::DbPool;
+
+use diesel::dsl::{count_distinct, count_star};
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use rocket::http::{ContentType, Status};
+use rocket::response::status::{Created, Custom};
+use rocket::serde::json::Json;
+use rocket::serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Insertable, Deserialize)]
+#[diesel(table_name = crate::schema::surveys)]
+pub struct NewSurvey {
+    pub title: String,
+    pub description: Option<String>,
+    pub closes_at: SystemTime,
+}
+
+#[derive(Deserialize, AsChangeset)]
+#[diesel(table_name = crate::schema::surveys)]
+pub struct UpdateSurvey {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub closes_at: Option<SystemTime>,
+}
+
+#[derive(Insertable, Deserialize)]
+#[diesel(table_name = crate::schema::survey_items)]
+pub struct NewSurveyItem {
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+}
+
+#[derive(Queryable, Serialize, Deserialize)]
+pub struct SurveyAnswer {
+    pub survey_item_id: i32,
+    pub quantity: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::survey_responses)]
+pub struct InsertSurveyResponse {
+    pub survey_item_id: i32,
+    pub user_id: i32,
+    pub quantity: i32,
+}
+
+#[derive(Serialize)]
+pub struct SurveyItemInfo {
+    pub id: i32,
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub name: Option<String>,
+    pub price: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct FullSurvey {
+    pub id: i32,
+    pub circle_id: i32,
+    pub title: String,
+    pub description: Option<String>,
+    pub closes_at: SystemTime,
+    pub is_open: bool,
+    pub items: Vec<SurveyItemInfo>,
+}
+
+#[derive(Serialize)]
+pub struct SurveyItemResult {
+    pub survey_item_id: i32,
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub name: Option<String>,
+    pub respondents: i64,
+    pub quantity: i64,
+}
+
+#[derive(Serialize)]
+pub struct SurveyResults {
+    pub survey_id: i32,
+    pub respondents: i64,
+    pub items: Vec<SurveyItemResult>,
+}
+
+fn find_survey(
+    survey_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Survey, CustomError> {
+    use crate::schema::surveys;
+
+    surveys::table
+        .find(survey_id)
+        .first::<Survey>(conn)
+        .map_err(handle_error)
+}
+
+/// Looks up a survey and checks that `user` may manage its circle.
+fn find_managed_survey(
+    user: &AuthenticatedUser,
+    survey_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Survey, CustomError> {
+    let survey = find_survey(survey_id, conn)?;
+
+    user.check_permission(survey.circle_id)?;
+
+    Ok(survey)
+}
+
+fn load_survey_items(
+    survey_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<SurveyItemInfo>, CustomError> {
+    use crate::schema::bundles;
+    use crate::schema::goods;
+    use crate::schema::survey_items;
+
+    Ok(survey_items::table
+        .left_join(goods::table)
+        .left_join(bundles::table)
+        .filter(survey_items::survey_id.eq(survey_id))
+        .order(survey_items::id)
+        .select((
+            survey_items::all_columns,
+            goods::name.nullable(),
+            goods::price.nullable(),
+            bundles::name.nullable(),
+            bundles::price.nullable(),
+        ))
+        .load::<(
+            SurveyItem,
+            Option<String>,
+            Option<i32>,
+            Option<String>,
+            Option<i32>,
+        )>(conn)
+        .map_err(handle_error)?
+        .into_iter()
+        .map(
+            |(item, goods_name, goods_price, bundle_name, bundle_price)| SurveyItemInfo {
+                id: item.id,
+                goods_id: item.goods_id,
+                bundle_id: item.bundle_id,
+                name: goods_name.or(bundle_name),
+                price: goods_price.or(bundle_price),
+            },
+        )
+        .collect())
+}
+
+fn load_survey_results(
+    survey_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<SurveyResults, CustomError> {
+    use crate::schema::survey_items;
+    use crate::schema::survey_responses;
+
+    let respondents = survey_responses::table
+        .inner_join(survey_items::table)
+        .filter(survey_items::survey_id.eq(survey_id))
+        .select(count_distinct(survey_responses::user_id))
+        .first::<i64>(conn)
+        .map_err(handle_error)?;
+
+    let mut counts = survey_responses::table
+        .inner_join(survey_items::table)
+        .filter(survey_items::survey_id.eq(survey_id))
+        .group_by(survey_responses::survey_item_id)
+        .select((
+            survey_responses::survey_item_id,
+            count_star(),
+            diesel::dsl::sum(survey_responses::quantity),
+        ))
+        .load::<(i32, i64, Option<i64>)>(conn)
+        .map_err(handle_error)?
+        .into_iter()
+        .map(|(survey_item_id, respondents, quantity)| {
+            (survey_item_id, (respondents, quantity.unwrap_or(0)))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let items = load_survey_items(survey_id, conn)?
+        .into_iter()
+        .map(|item| {
+            let (respondents, quantity) = counts.remove(&item.id).unwrap_or((0, 0));
+
+            SurveyItemResult {
+                survey_item_id: item.id,
+                goods_id: item.goods_id,
+                bundle_id: item.bundle_id,
+                name: item.name,
+                respondents,
+                quantity,
+            }
+        })
+        .collect();
+
+    Ok(SurveyResults {
+        survey_id,
+        respondents,
+        items,
+    })
+}
+
+#[post("/circles/<circle_id>/surveys", format = "json", data = "<new_survey>")]
+pub fn post_survey(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    new_survey: Json<NewSurvey>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<Json<Survey>>, CustomError> {
+    use crate::schema::surveys;
+
+    user.check_permission(circle_id)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let survey = diesel::insert_into(surveys::table)
+        .values((surveys::circle_id.eq(circle_id), new_survey.into_inner()))
+        .get_result::<Survey>(&mut conn)
+        .map_err(handle_error)?;
+
+    Ok(Created::new(format!("/surveys/{}", survey.id)).body(Json(survey)))
+}
+
+#[get("/circles/<circle_id>/surveys")]
+pub fn get_circle_surveys(
+    circle_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Vec<Survey>>, CustomError> {
+    use crate::schema::surveys;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    surveys::table
+        .filter(surveys::circle_id.eq(circle_id))
+        .order((surveys::closes_at.desc(), surveys::id))
+        .load::<Survey>(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
+
+#[get("/surveys/<survey_id>")]
+pub fn get_survey_by_id(
+    survey_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<FullSurvey>, CustomError> {
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let survey = find_survey(survey_id, &mut conn)?;
+    let items = load_survey_items(survey.id, &mut conn)?;
+
+    Ok(Json(FullSurvey {
+        is_open: survey.is_open(),
+        id: survey.id,
+        circle_id: survey.circle_id,
+        title: survey.title,
+        description: survey.description,
+        closes_at: survey.closes_at,
+        items,
+    }))
+}
+
+#[patch("/surveys/<survey_id>", format = "json", data = "<update_survey>")]
+pub fn patch_survey(
+    user: AuthenticatedUser,
+    survey_id: i32,
+    update_survey: Json<UpdateSurvey>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Survey>, CustomError> {
+    use crate::schema::surveys;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_managed_survey(&user, survey_id, &mut conn)?;
+
+    diesel::update(surveys::table.find(survey_id))
+        .set(update_survey.into_inner())
+        .get_result::<Survey>(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
+
+#[delete("/surveys/<survey_id>")]
+pub fn delete_survey(
+    user: AuthenticatedUser,
+    survey_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::surveys;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_managed_survey(&user, survey_id, &mut conn)?;
+
+    let size = diesel::delete(surveys::table.find(survey_id))
+        .execute(&mut conn)
+        .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[post("/surveys/<survey_id>/items", format = "json", data = "<new_item>")]
+pub fn post_survey_item(
+    user: AuthenticatedUser,
+    survey_id: i32,
+    new_item: Json<NewSurveyItem>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<Json<SurveyItem>>, CustomError> {
+    use crate::schema::survey_items;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let survey = find_managed_survey(&user, survey_id, &mut conn)?;
+
+    check_circle_item(
+        survey.circle_id,
+        new_item.goods_id,
+        new_item.bundle_id,
+        &mut conn,
+    )?;
+
+    let item = diesel::insert_into(survey_items::table)
+        .values((survey_items::survey_id.eq(survey_id), new_item.into_inner()))
+        .get_result::<SurveyItem>(&mut conn)
+        .map_err(handle_error)?;
+
+    Ok(Created::new(format!("/surveys/{}/items/{}", survey_id, item.id)).body(Json(item)))
+}
+
+#[delete("/surveys/<survey_id>/items/<item_id>")]
+pub fn delete_survey_item(
+    user: AuthenticatedUser,
+    survey_id: i32,
+    item_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::survey_items;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_managed_survey(&user, survey_id, &mut conn)?;
+
+    let size = diesel::delete(
+        survey_items::table
+            .find(item_id)
+            .filter(survey_items::survey_id.eq(survey_id)),
+    )
+    .execute(&mut conn)
+    .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[get("/surveys/<survey_id>/responses/me")]
+pub fn get_my_survey_response(
+    user: AuthenticatedUser,
+    survey_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Vec<SurveyAnswer>>, CustomError> {
+    use crate::schema::survey_items;
+    use crate::schema::survey_responses;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    survey_responses::table
+        .inner_join(survey_items::table)
+        .filter(survey_items::survey_id.eq(survey_id))
+        .filter(survey_responses::user_id.eq(user.id))
+        .order(survey_responses::survey_item_id)
+        .select((survey_responses::survey_item_id, survey_responses::quantity))
+        .load::<SurveyAnswer>(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
+
+/// Replaces the user's answers. Items left out, or answered with a quantity
+/// of zero, are cleared.
+#[put(
+    "/surveys/<survey_id>/responses/me",
+    format = "json",
+    data = "<answers>"
+)]
+pub fn put_my_survey_response(
+    user: AuthenticatedUser,
+    survey_id: i32,
+    answers: Json<Vec<SurveyAnswer>>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Vec<SurveyAnswer>>, CustomError> {
+    use crate::schema::survey_items;
+    use crate::schema::survey_responses;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let survey = find_survey(survey_id, &mut conn)?;
+
+    if !survey.is_open() {
+        return Err(Custom(
+            Status::Conflict,
+            Json(ErrorInfo::new("Survey is closed".into())),
+        ));
+    }
+
+    let item_ids = survey_items::table
+        .filter(survey_items::survey_id.eq(survey_id))
+        .select(survey_items::id)
+        .load::<i32>(&mut conn)
+        .map_err(handle_error)?;
+
+    let mut seen = HashSet::new();
+
+    for answer in answers.iter() {
+        if !item_ids.contains(&answer.survey_item_id) {
+            return Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new(format!(
+                    "Unknown survey item {}",
+                    answer.survey_item_id
+                ))),
+            ));
+        }
+
+        if !seen.insert(answer.survey_item_id) {
+            return Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new(format!(
+                    "Survey item {} is answered twice",
+                    answer.survey_item_id
+                ))),
+            ));
+        }
+
+        if answer.quantity < 0 {
+            return Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new("Quantity cannot be negative".into())),
+            ));
+        }
+    }
+
+    let answers = answers
+        .into_inner()
+        .into_iter()
+        .filter(|answer| answer.quantity > 0)
+        .collect::<Vec<_>>();
+
+    conn.transaction(|conn| {
+        diesel::delete(
+            survey_responses::table
+                .filter(survey_responses::user_id.eq(user.id))
+                .filter(survey_responses::survey_item_id.eq_any(&item_ids)),
+        )
+        .execute(conn)?;
+
+        diesel::insert_into(survey_responses::table)
+            .values(
+                answers
+                    .iter()
+                    .map(|answer| InsertSurveyResponse {
+                        survey_item_id: answer.survey_item_id,
+                        user_id: user.id,
+                        quantity: answer.quantity,
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .execute(conn)
+    })
+    .map_err(handle_error)?;
+
+    Ok(Json(answers))
+}
+
+#[get("/surveys/<survey_id>/results")]
+pub fn get_survey_results(
+    user: AuthenticatedUser,
+    survey_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<SurveyResults>, CustomError> {
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_managed_survey(&user, survey_id, &mut conn)?;
+
+    load_survey_results(survey_id, &mut conn).map(Json)
+}
+
+#[get("/surveys/<survey_id>/results.csv")]
+pub fn get_survey_results_csv(
+    user: AuthenticatedUser,
+    survey_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<(ContentType, String), CustomError> {
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_managed_survey(&user, survey_id, &mut conn)?;
+
+    let results = load_survey_results(survey_id, &mut conn)?;
+
+    let mut body = csv::row([
+        "survey_item_id",
+        "goods_id",
+        "bundle_id",
+        "name",
+        "respondents",
+        "quantity",
+    ]);
+
+    for item in results.items {
+        body.push_str(&csv::row([
+            item.survey_item_id.to_string(),
+            item.goods_id.map(|id| id.to_string()).unwrap_or_default(),
+            item.bundle_id.map(|id| id.to_string()).unwrap_or_default(),
+            csv::text(item.name.unwrap_or_default()),
+            item.respondents.to_string(),
+            item.quantity.to_string(),
+        ]));
+    }
+
+    Ok((ContentType::CSV, body))
+}
@@ -0,0 +1,540 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::time::SystemTime;
+
+use crate::error_handler::{handle_error, CustomError, ErrorInfo};
+use crate::models::{AuthenticatedUser, Tally};
+use crate::routes::orders::check_circle_item;
+use crate::utils::csv;
+use crate::DbPool;
+
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use rocket::http::{ContentType, Status};
+use rocket::response::status::{Created, Custom};
+use rocket::serde::json::Json;
+use rocket::serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Deserialize)]
+pub struct NewTally {
+    pub title: String,
+    pub event_id: Option<i32>,
+    pub items: Vec<NewTallyItem>,
+}
+
+#[derive(Deserialize)]
+pub struct NewTallyItem {
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub starting: i32,
+    pub remaining: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::tallies)]
+pub struct InsertTally {
+    pub circle_id: i32,
+    pub event_id: Option<i32>,
+    pub title: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::tally_items)]
+pub struct InsertTallyItem {
+    pub tally_id: i32,
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub starting: i32,
+    pub remaining: i32,
+    pub price: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct TallyLine {
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub name: Option<String>,
+    pub starting: i32,
+    pub remaining: i32,
+    pub sold: i32,
+    pub price: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct FullTally {
+    pub id: i32,
+    pub circle_id: i32,
+    pub event_id: Option<i32>,
+    pub title: String,
+    pub created_at: SystemTime,
+    pub items: Vec<TallyLine>,
+}
+
+/// One goods or bundle in a report. `starting` and `remaining` are missing
+/// for goods that were not counted themselves but went out inside bundles.
+#[derive(Serialize)]
+pub struct TallyReportLine {
+    pub goods_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub name: Option<String>,
+    pub starting: Option<i64>,
+    pub remaining: Option<i64>,
+    pub sold: i64,
+    pub revenue: i64,
+    pub sold_in_bundles: i64,
+    pub units: i64,
+}
+
+#[derive(Serialize)]
+pub struct TallyReport {
+    pub circle_id: i32,
+    pub event_id: Option<i32>,
+    pub tallies: i64,
+    pub revenue: i64,
+    pub items: Vec<TallyReportLine>,
+}
+
+#[derive(Default)]
+struct Counted {
+    starting: i64,
+    remaining: i64,
+    revenue: i64,
+}
+
+impl Counted {
+    fn sold(&self) -> i64 {
+        self.starting - self.remaining
+    }
+}
+
+/// Looks up a tally and checks that `user` may manage its circle.
+fn find_managed_tally(
+    user: &AuthenticatedUser,
+    tally_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Tally, CustomError> {
+    use crate::schema::tallies;
+
+    let tally = tallies::table
+        .find(tally_id)
+        .first::<Tally>(conn)
+        .map_err(handle_error)?;
+
+    user.check_permission(tally.circle_id)?;
+
+    Ok(tally)
+}
+
+fn load_full_tally(
+    tally: Tally,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<FullTally, CustomError> {
+    use crate::schema::bundles;
+    use crate::schema::goods;
+    use crate::schema::tally_items;
+
+    let items = tally_items::table
+        .left_join(goods::table)
+        .left_join(bundles::table)
+        .filter(tally_items::tally_id.eq(tally.id))
+        .order(tally_items::id)
+        .select((
+            tally_items::goods_id,
+            tally_items::bundle_id,
+            goods::name.nullable(),
+            bundles::name.nullable(),
+            tally_items::starting,
+            tally_items::remaining,
+            tally_items::price,
+        ))
+        .load::<(
+            Option<i32>,
+            Option<i32>,
+            Option<String>,
+            Option<String>,
+            i32,
+            i32,
+            Option<i32>,
+        )>(conn)
+        .map_err(handle_error)?
+        .into_iter()
+        .map(
+            |(goods_id, bundle_id, goods_name, bundle_name, starting, remaining, price)| {
+                TallyLine {
+                    goods_id,
+                    bundle_id,
+                    name: goods_name.or(bundle_name),
+                    starting,
+                    remaining,
+                    sold: starting - remaining,
+                    price,
+                }
+            },
+        )
+        .collect();
+
+    Ok(FullTally {
+        id: tally.id,
+        circle_id: tally.circle_id,
+        event_id: tally.event_id,
+        title: tally.title,
+        created_at: tally.created_at,
+        items,
+    })
+}
+
+/// Adds up every tally of the circle, or only those of one event.
+///
+/// Revenue uses the prices the items had when each tally was uploaded, with
+/// unpriced items counting as zero. Sold bundles are broken down into their
+/// goods by `goods_in_bundle.count` and show up in the goods' `units`.
+fn load_tally_report(
+    circle_id: i32,
+    event_id: Option<i32>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<TallyReport, CustomError> {
+    use crate::schema::bundles;
+    use crate::schema::goods;
+    use crate::schema::goods_in_bundle;
+    use crate::schema::tallies;
+    use crate::schema::tally_items;
+
+    let mut query = tallies::table
+        .filter(tallies::circle_id.eq(circle_id))
+        .select(tallies::id)
+        .into_boxed();
+
+    if let Some(event_id) = event_id {
+        query = query.filter(tallies::event_id.eq(event_id));
+    }
+
+    let tally_ids = query.load::<i32>(conn).map_err(handle_error)?;
+
+    let rows = tally_items::table
+        .filter(tally_items::tally_id.eq_any(&tally_ids))
+        .select((
+            tally_items::goods_id,
+            tally_items::bundle_id,
+            tally_items::starting,
+            tally_items::remaining,
+            tally_items::price,
+        ))
+        .load::<(Option<i32>, Option<i32>, i32, i32, Option<i32>)>(conn)
+        .map_err(handle_error)?;
+
+    let mut counted_goods: BTreeMap<i32, Counted> = BTreeMap::new();
+    let mut counted_bundles: BTreeMap<i32, Counted> = BTreeMap::new();
+
+    for (goods_id, bundle_id, starting, remaining, price) in rows {
+        let counted = match (goods_id, bundle_id) {
+            (Some(goods_id), _) => counted_goods.entry(goods_id).or_default(),
+            (_, Some(bundle_id)) => counted_bundles.entry(bundle_id).or_default(),
+            _ => continue,
+        };
+
+        counted.starting += i64::from(starting);
+        counted.remaining += i64::from(remaining);
+        counted.revenue += i64::from(starting - remaining) * i64::from(price.unwrap_or(0));
+    }
+
+    let mut sold_in_bundles: BTreeMap<i32, i64> = BTreeMap::new();
+
+    for (bundle_id, goods_id, count) in goods_in_bundle::table
+        .filter(goods_in_bundle::bundle_id.eq_any(counted_bundles.keys().collect::<Vec<_>>()))
+        .select((
+            goods_in_bundle::bundle_id,
+            goods_in_bundle::goods_id,
+            goods_in_bundle::count,
+        ))
+        .load::<(i32, i32, i32)>(conn)
+        .map_err(handle_error)?
+    {
+        let sold = counted_bundles
+            .get(&bundle_id)
+            .map(Counted::sold)
+            .unwrap_or(0);
+
+        *sold_in_bundles.entry(goods_id).or_default() += sold * i64::from(count);
+    }
+
+    let goods_ids = counted_goods
+        .keys()
+        .chain(sold_in_bundles.keys())
+        .copied()
+        .collect::<BTreeSet<_>>();
+
+    let goods_names = goods::table
+        .filter(goods::id.eq_any(&goods_ids))
+        .select((goods::id, goods::name))
+        .load::<(i32, Option<String>)>(conn)
+        .map_err(handle_error)?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+    let bundle_names = bundles::table
+        .filter(bundles::id.eq_any(counted_bundles.keys().collect::<Vec<_>>()))
+        .select((bundles::id, bundles::name))
+        .load::<(i32, Option<String>)>(conn)
+        .map_err(handle_error)?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+    let goods_lines = goods_ids.into_iter().map(|goods_id| {
+        let counted = counted_goods.get(&goods_id);
+        let sold = counted.map(Counted::sold).unwrap_or(0);
+        let in_bundles = sold_in_bundles.get(&goods_id).copied().unwrap_or(0);
+
+        TallyReportLine {
+            goods_id: Some(goods_id),
+            bundle_id: None,
+            name: goods_names.get(&goods_id).cloned().flatten(),
+            starting: counted.map(|counted| counted.starting),
+            remaining: counted.map(|counted| counted.remaining),
+            sold,
+            revenue: counted.map(|counted| counted.revenue).unwrap_or(0),
+            sold_in_bundles: in_bundles,
+            units: sold + in_bundles,
+        }
+    });
+
+    let bundle_lines = counted_bundles
+        .iter()
+        .map(|(bundle_id, counted)| TallyReportLine {
+            goods_id: None,
+            bundle_id: Some(*bundle_id),
+            name: bundle_names.get(bundle_id).cloned().flatten(),
+            starting: Some(counted.starting),
+            remaining: Some(counted.remaining),
+            sold: counted.sold(),
+            revenue: counted.revenue,
+            sold_in_bundles: 0,
+            units: counted.sold(),
+        });
+
+    let items = goods_lines.chain(bundle_lines).collect::<Vec<_>>();
+
+    Ok(TallyReport {
+        circle_id,
+        event_id,
+        tallies: tally_ids.len() as i64,
+        revenue: items.iter().map(|line| line.revenue).sum(),
+        items,
+    })
+}
+
+/// Uploads the starting and end-of-day remaining counts of a circle's goods
+/// and bundles.
+#[post("/circles/<circle_id>/tallies", format = "json", data = "<new_tally>")]
+pub fn post_tally(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    new_tally: Json<NewTally>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<Json<FullTally>>, CustomError> {
+    use crate::schema::bundles;
+    use crate::schema::goods;
+    use crate::schema::tallies;
+    use crate::schema::tally_items;
+
+    user.check_permission(circle_id)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    if new_tally.items.is_empty() {
+        return Err(Custom(
+            Status::BadRequest,
+            Json(ErrorInfo::new("Tally has no items".into())),
+        ));
+    }
+
+    let mut seen = HashSet::new();
+    let mut prices = Vec::with_capacity(new_tally.items.len());
+
+    for item in new_tally.items.iter() {
+        check_circle_item(circle_id, item.goods_id, item.bundle_id, &mut conn)?;
+
+        if !seen.insert((item.goods_id, item.bundle_id)) {
+            return Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new("Item is listed twice".into())),
+            ));
+        }
+
+        if item.remaining < 0 || item.remaining > item.starting {
+            return Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new(
+                    "Remaining count must be between 0 and the starting count".into(),
+                )),
+            ));
+        }
+
+        let price = match (item.goods_id, item.bundle_id) {
+            (Some(goods_id), _) => goods::table
+                .find(goods_id)
+                .select(goods::price)
+                .first::<Option<i32>>(&mut conn),
+            (_, Some(bundle_id)) => bundles::table
+                .find(bundle_id)
+                .select(bundles::price)
+                .first::<Option<i32>>(&mut conn),
+            _ => Err(diesel::result::Error::NotFound),
+        }
+        .map_err(handle_error)?;
+
+        prices.push(price);
+    }
+
+    let new_tally = new_tally.into_inner();
+
+    let tally = conn
+        .transaction(|conn| {
+            let tally = diesel::insert_into(tallies::table)
+                .values(InsertTally {
+                    circle_id,
+                    event_id: new_tally.event_id,
+                    title: new_tally.title,
+                })
+                .get_result::<Tally>(conn)?;
+
+            diesel::insert_into(tally_items::table)
+                .values(
+                    new_tally
+                        .items
+                        .iter()
+                        .zip(prices)
+                        .map(|(item, price)| InsertTallyItem {
+                            tally_id: tally.id,
+                            goods_id: item.goods_id,
+                            bundle_id: item.bundle_id,
+                            starting: item.starting,
+                            remaining: item.remaining,
+                            price,
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .execute(conn)?;
+
+            Ok(tally)
+        })
+        .map_err(handle_error)?;
+
+    let tally = load_full_tally(tally, &mut conn)?;
+
+    Ok(Created::new(format!("/tallies/{}", tally.id)).body(Json(tally)))
+}
+
+#[get("/circles/<circle_id>/tallies")]
+pub fn get_circle_tallies(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Vec<Tally>>, CustomError> {
+    use crate::schema::tallies;
+
+    user.check_permission(circle_id)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    tallies::table
+        .filter(tallies::circle_id.eq(circle_id))
+        .order((tallies::created_at.desc(), tallies::id))
+        .load::<Tally>(&mut conn)
+        .map(Json)
+        .map_err(handle_error)
+}
+
+#[get("/tallies/<tally_id>")]
+pub fn get_tally_by_id(
+    user: AuthenticatedUser,
+    tally_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<FullTally>, CustomError> {
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let tally = find_managed_tally(&user, tally_id, &mut conn)?;
+
+    load_full_tally(tally, &mut conn).map(Json)
+}
+
+#[delete("/tallies/<tally_id>")]
+pub fn delete_tally(
+    user: AuthenticatedUser,
+    tally_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::tallies;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    find_managed_tally(&user, tally_id, &mut conn)?;
+
+    let size = diesel::delete(tallies::table.find(tally_id))
+        .execute(&mut conn)
+        .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[get("/circles/<circle_id>/tallies/report?<event_id>")]
+pub fn get_tally_report(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    event_id: Option<i32>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<TallyReport>, CustomError> {
+    user.check_permission(circle_id)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    load_tally_report(circle_id, event_id, &mut conn).map(Json)
+}
+
+#[get("/circles/<circle_id>/tallies/report.csv?<event_id>")]
+pub fn get_tally_report_csv(
+    user: AuthenticatedUser,
+    circle_id: i32,
+    event_id: Option<i32>,
+    pool: &rocket::State<DbPool>,
+) -> Result<(ContentType, String), CustomError> {
+    user.check_permission(circle_id)?;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let report = load_tally_report(circle_id, event_id, &mut conn)?;
+
+    let mut body = csv::row([
+        "goods_id",
+        "bundle_id",
+        "name",
+        "starting",
+        "remaining",
+        "sold",
+        "revenue",
+        "sold_in_bundles",
+        "units",
+    ]);
+
+    for line in report.items {
+        body.push_str(&csv::row([
+            line.goods_id.map(|id| id.to_string()).unwrap_or_default(),
+            line.bundle_id.map(|id| id.to_string()).unwrap_or_default(),
+            csv::text(line.name.unwrap_or_default()),
+            line.starting.map(|n| n.to_string()).unwrap_or_default(),
+            line.remaining.map(|n| n.to_string()).unwrap_or_default(),
+            line.sold.to_string(),
+            line.revenue.to_string(),
+            line.sold_in_bundles.to_string(),
+            line.units.to_string(),
+        ]));
+    }
+
+    Ok((ContentType::CSV, body))
+}
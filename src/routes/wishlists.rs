@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+
+use crate::error_handler::{handle_error, CustomError, ErrorInfo};
+use crate::models::{AuthenticatedUser, Bundle, FullGood, Good};
+use crate::routes::goods::load_full_goods_with;
+use crate::DbPool;
+
+use diesel::prelude::*;
+use rocket::http::Status;
+use rocket::response::status::{Created, Custom};
+use rocket::serde::json::Json;
+use rocket::serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct WishlistGood {
+    pub quantity: i32,
+    pub goods: FullGood,
+}
+
+#[derive(Serialize)]
+pub struct WishlistBundle {
+    pub quantity: i32,
+    pub bundle: Bundle,
+}
+
+#[derive(Serialize)]
+pub struct WishlistCircle {
+    pub circle_id: i32,
+    pub circle_name: Option<String>,
+    pub goods: Vec<WishlistGood>,
+    pub bundles: Vec<WishlistBundle>,
+    pub total: i64,
+}
+
+#[derive(Serialize)]
+pub struct Wishlist {
+    pub circles: Vec<WishlistCircle>,
+    pub total: i64,
+}
+
+#[get("/users/me/wishlist")]
+pub fn get_wishlist(
+    user: AuthenticatedUser,
+    pool: &rocket::State<DbPool>,
+) -> Result<Json<Wishlist>, CustomError> {
+    use crate::schema::bundles;
+    use crate::schema::circle_bundles;
+    use crate::schema::circles;
+    use crate::schema::goods;
+    use crate::schema::wishlist_bundles;
+    use crate::schema::wishlist_goods;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let goods = wishlist_goods::table
+        .inner_join(goods::table)
+        .filter(wishlist_goods::user_id.eq(user.id))
+        .order(wishlist_goods::id)
+        .select((wishlist_goods::quantity, goods::all_columns))
+        .load::<(i32, Good)>(&mut conn)
+        .map_err(handle_error)?;
+
+    let full_goods = load_full_goods_with(goods, &mut conn)?;
+
+    let bundles = wishlist_bundles::table
+        .inner_join(bundles::table)
+        .inner_join(
+            circle_bundles::table.on(circle_bundles::bundle_id.eq(wishlist_bundles::bundle_id)),
+        )
+        .filter(wishlist_bundles::user_id.eq(user.id))
+        .order(wishlist_bundles::id)
+        .select((
+            wishlist_bundles::quantity,
+            circle_bundles::circle_id,
+            bundles::all_columns,
+        ))
+        .load::<(i32, i32, Bundle)>(&mut conn)
+        .map_err(handle_error)?;
+
+    let circle_ids = full_goods
+        .iter()
+        .map(|(_, good)| good.circle_id)
+        .chain(bundles.iter().map(|(_, circle_id, _)| *circle_id))
+        .collect::<Vec<_>>();
+
+    let mut wishlist = circles::table
+        .filter(circles::id.eq_any(circle_ids))
+        .select((circles::id, circles::name))
+        .load::<(i32, Option<String>)>(&mut conn)
+        .map_err(handle_error)?
+        .into_iter()
+        .map(|(circle_id, circle_name)| {
+            (
+                circle_id,
+                WishlistCircle {
+                    circle_id,
+                    circle_name,
+                    goods: vec![],
+                    bundles: vec![],
+                    total: 0,
+                },
+            )
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    for (quantity, good) in full_goods {
+        if let Some(circle) = wishlist.get_mut(&good.circle_id) {
+            circle.total += i64::from(good.price.unwrap_or(0)) * i64::from(quantity);
+            circle.goods.push(WishlistGood {
+                quantity,
+                goods: good,
+            });
+        }
+    }
+
+    for (quantity, circle_id, bundle) in bundles {
+        if let Some(circle) = wishlist.get_mut(&circle_id) {
+            circle.total += i64::from(bundle.price.unwrap_or(0)) * i64::from(quantity);
+            circle.bundles.push(WishlistBundle { quantity, bundle });
+        }
+    }
+
+    let circles = wishlist.into_values().collect::<Vec<_>>();
+    let total = circles.iter().map(|circle| circle.total).sum();
+
+    Ok(Json(Wishlist { circles, total }))
+}
+
+#[derive(Deserialize)]
+pub struct NewWishlistGood {
+    pub goods_id: i32,
+    pub quantity: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct NewWishlistBundle {
+    pub bundle_id: i32,
+    pub quantity: Option<i32>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::wishlist_goods)]
+pub struct InsertWishlistGood {
+    pub user_id: i32,
+    pub goods_id: i32,
+    pub quantity: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::wishlist_bundles)]
+pub struct InsertWishlistBundle {
+    pub user_id: i32,
+    pub bundle_id: i32,
+    pub quantity: i32,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateWishlistItem {
+    pub quantity: i32,
+}
+
+#[post("/users/me/wishlist/goods", format = "json", data = "<new_item>")]
+pub fn post_wishlist_goods(
+    user: AuthenticatedUser,
+    new_item: Json<NewWishlistGood>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<()>, CustomError> {
+    use crate::schema::wishlist_goods;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    diesel::insert_into(wishlist_goods::table)
+        .values(InsertWishlistGood {
+            user_id: user.id,
+            goods_id: new_item.goods_id,
+            quantity: new_item.quantity.unwrap_or(1),
+        })
+        .execute(&mut conn)
+        .map_err(handle_error)?;
+
+    Ok(Created::new(format!(
+        "/users/me/wishlist/goods/{}",
+        new_item.goods_id
+    )))
+}
+
+#[patch(
+    "/users/me/wishlist/goods/<goods_id>",
+    format = "json",
+    data = "<update_item>"
+)]
+pub fn patch_wishlist_goods(
+    user: AuthenticatedUser,
+    goods_id: i32,
+    update_item: Json<UpdateWishlistItem>,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::wishlist_goods;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let size = diesel::update(
+        wishlist_goods::table
+            .filter(wishlist_goods::user_id.eq(user.id))
+            .filter(wishlist_goods::goods_id.eq(goods_id)),
+    )
+    .set(wishlist_goods::quantity.eq(update_item.quantity))
+    .execute(&mut conn)
+    .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[delete("/users/me/wishlist/goods/<goods_id>")]
+pub fn delete_wishlist_goods(
+    user: AuthenticatedUser,
+    goods_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::wishlist_goods;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let size = diesel::delete(
+        wishlist_goods::table
+            .filter(wishlist_goods::user_id.eq(user.id))
+            .filter(wishlist_goods::goods_id.eq(goods_id)),
+    )
+    .execute(&mut conn)
+    .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[post("/users/me/wishlist/bundles", format = "json", data = "<new_item>")]
+pub fn post_wishlist_bundle(
+    user: AuthenticatedUser,
+    new_item: Json<NewWishlistBundle>,
+    pool: &rocket::State<DbPool>,
+) -> Result<Created<()>, CustomError> {
+    use crate::schema::wishlist_bundles;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    diesel::insert_into(wishlist_bundles::table)
+        .values(InsertWishlistBundle {
+            user_id: user.id,
+            bundle_id: new_item.bundle_id,
+            quantity: new_item.quantity.unwrap_or(1),
+        })
+        .execute(&mut conn)
+        .map_err(handle_error)?;
+
+    Ok(Created::new(format!(
+        "/users/me/wishlist/bundles/{}",
+        new_item.bundle_id
+    )))
+}
+
+#[patch(
+    "/users/me/wishlist/bundles/<bundle_id>",
+    format = "json",
+    data = "<update_item>"
+)]
+pub fn patch_wishlist_bundle(
+    user: AuthenticatedUser,
+    bundle_id: i32,
+    update_item: Json<UpdateWishlistItem>,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::wishlist_bundles;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let size = diesel::update(
+        wishlist_bundles::table
+            .filter(wishlist_bundles::user_id.eq(user.id))
+            .filter(wishlist_bundles::bundle_id.eq(bundle_id)),
+    )
+    .set(wishlist_bundles::quantity.eq(update_item.quantity))
+    .execute(&mut conn)
+    .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[delete("/users/me/wishlist/bundles/<bundle_id>")]
+pub fn delete_wishlist_bundle(
+    user: AuthenticatedUser,
+    bundle_id: i32,
+    pool: &rocket::State<DbPool>,
+) -> Result<(), CustomError> {
+    use crate::schema::wishlist_bundles;
+
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let size = diesel::delete(
+        wishlist_bundles::table
+            .filter(wishlist_bundles::user_id.eq(user.id))
+            .filter(wishlist_bundles::bundle_id.eq(bundle_id)),
+    )
+    .execute(&mut conn)
+    .map_err(handle_error)?;
+
+    if size == 0 {
+        Err(Custom(
+            Status::NotFound,
+            Json(ErrorInfo::new("not_found".to_string())),
+        ))
+    } else {
+        Ok(())
+    }
+}
@@ -1,6 +1,14 @@
 // @generated automatically by Diesel CLI.
 
 pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "alias_kind_type"))]
+    pub struct AliasKindType;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "availability_type"))]
+    pub struct AvailabilityType;
+
     #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "bundle_type"))]
     pub struct BundleType;
@@ -9,6 +17,10 @@ pub mod sql_types {
     #[diesel(postgres_type(name = "link_type"))]
     pub struct LinkType;
 
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "match_status_type"))]
+    pub struct MatchStatusType;
+
     #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "role_type"))]
     pub struct RoleType;
@@ -27,6 +39,7 @@ diesel::table! {
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::BundleType;
+    use super::sql_types::AvailabilityType;
 
     bundles (id) {
         id -> Int4,
@@ -38,6 +51,8 @@ diesel::table! {
         #[sql_name = "type"]
         type_ -> BundleType,
         count -> Int4,
+        stock -> Nullable<Int4>,
+        availability -> AvailabilityType,
     }
 }
 
@@ -49,6 +64,21 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::AliasKindType;
+
+    character_aliases (id) {
+        id -> Int4,
+        character_id -> Int4,
+        #[max_length = 16]
+        language -> Varchar,
+        kind -> AliasKindType,
+        #[max_length = 255]
+        name -> Varchar,
+    }
+}
+
 diesel::table! {
     characters (id) {
         id -> Int4,
@@ -74,6 +104,16 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    circle_events (id) {
+        id -> Int4,
+        circle_id -> Int4,
+        event_id -> Int4,
+        #[max_length = 255]
+        location -> Nullable<Varchar>,
+    }
+}
+
 diesel::table! {
     circle_goods (id) {
         circle_id -> Int4,
@@ -98,10 +138,54 @@ diesel::table! {
         description -> Nullable<Text>,
         #[max_length = 255]
         location -> Nullable<Varchar>,
+        #[max_length = 16]
+        booth_hall -> Nullable<Varchar>,
+        #[max_length = 16]
+        booth_block -> Nullable<Varchar>,
+        booth_space -> Nullable<Int4>,
+        #[max_length = 2]
+        booth_half -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    collection_goods (id) {
+        id -> Int4,
+        collection_id -> Int4,
+        goods_id -> Int4,
+        position -> Int4,
+        note -> Nullable<Text>,
     }
 }
 
 diesel::table! {
+    collections (id) {
+        id -> Int4,
+        user_id -> Int4,
+        #[max_length = 255]
+        name -> Varchar,
+        description -> Nullable<Text>,
+        #[max_length = 24]
+        share_token -> Nullable<Bpchar>,
+    }
+}
+
+diesel::table! {
+    events (id) {
+        id -> Int4,
+        #[max_length = 255]
+        name -> Varchar,
+        #[max_length = 255]
+        venue -> Nullable<Varchar>,
+        start_date -> Timestamp,
+        end_date -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::AvailabilityType;
+
     goods (id) {
         id -> Int4,
         #[max_length = 255]
@@ -111,6 +195,8 @@ diesel::table! {
         category_id -> Int4,
         #[max_length = 16]
         image_name -> Nullable<Bpchar>,
+        stock -> Nullable<Int4>,
+        availability -> AvailabilityType,
     }
 }
 
@@ -128,6 +214,7 @@ diesel::table! {
         goods_id -> Int4,
         id -> Int4,
         count -> Int4,
+        weight -> Int4,
     }
 }
 
@@ -146,6 +233,118 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    order_form_items (id) {
+        id -> Int4,
+        order_form_id -> Int4,
+        goods_id -> Nullable<Int4>,
+        bundle_id -> Nullable<Int4>,
+        max_quantity -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    order_forms (id) {
+        id -> Int4,
+        circle_id -> Int4,
+        #[max_length = 255]
+        title -> Varchar,
+        description -> Nullable<Text>,
+        opens_at -> Timestamp,
+        closes_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    order_items (id) {
+        id -> Int4,
+        order_id -> Int4,
+        order_form_item_id -> Int4,
+        quantity -> Int4,
+        price -> Int4,
+    }
+}
+
+diesel::table! {
+    orders (id) {
+        id -> Int4,
+        order_form_id -> Int4,
+        user_id -> Int4,
+        note -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    prepayment_items (id) {
+        id -> Int4,
+        prepayment_id -> Int4,
+        goods_id -> Nullable<Int4>,
+        bundle_id -> Nullable<Int4>,
+        quantity -> Int4,
+    }
+}
+
+diesel::table! {
+    prepayments (id) {
+        id -> Int4,
+        circle_id -> Int4,
+        #[max_length = 255]
+        buyer_name -> Varchar,
+        #[max_length = 255]
+        contact -> Nullable<Varchar>,
+        note -> Nullable<Text>,
+        user_id -> Nullable<Int4>,
+        order_id -> Nullable<Int4>,
+        #[max_length = 8]
+        code -> Bpchar,
+        created_at -> Timestamp,
+        redeemed_at -> Nullable<Timestamp>,
+        redeemed_by -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::MatchStatusType;
+
+    reconciliation_deposits (id) {
+        id -> Int4,
+        reconciliation_id -> Int4,
+        line -> Int4,
+        #[max_length = 255]
+        depositor_name -> Varchar,
+        amount -> Int4,
+        #[max_length = 255]
+        deposited_at -> Nullable<Varchar>,
+        status -> MatchStatusType,
+        payment_id -> Nullable<Int4>,
+        candidate_ids -> Array<Int4>,
+    }
+}
+
+diesel::table! {
+    reconciliation_payments (id) {
+        id -> Int4,
+        reconciliation_id -> Int4,
+        #[max_length = 255]
+        depositor_name -> Varchar,
+        amount -> Int4,
+        #[max_length = 255]
+        note -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    reconciliations (id) {
+        id -> Int4,
+        circle_id -> Int4,
+        #[max_length = 255]
+        title -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     refs (id) {
         id -> Int4,
@@ -154,6 +353,79 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    sale_items (id) {
+        id -> Int4,
+        sale_id -> Int4,
+        goods_id -> Nullable<Int4>,
+        bundle_id -> Nullable<Int4>,
+        quantity -> Int4,
+        price -> Int4,
+    }
+}
+
+diesel::table! {
+    sales (id) {
+        id -> Int4,
+        circle_id -> Int4,
+        user_id -> Nullable<Int4>,
+        total -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    survey_items (id) {
+        id -> Int4,
+        survey_id -> Int4,
+        goods_id -> Nullable<Int4>,
+        bundle_id -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    survey_responses (id) {
+        id -> Int4,
+        survey_item_id -> Int4,
+        user_id -> Int4,
+        quantity -> Int4,
+    }
+}
+
+diesel::table! {
+    surveys (id) {
+        id -> Int4,
+        circle_id -> Int4,
+        #[max_length = 255]
+        title -> Varchar,
+        description -> Nullable<Text>,
+        closes_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    tallies (id) {
+        id -> Int4,
+        circle_id -> Int4,
+        event_id -> Nullable<Int4>,
+        #[max_length = 255]
+        title -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    tally_items (id) {
+        id -> Int4,
+        tally_id -> Int4,
+        goods_id -> Nullable<Int4>,
+        bundle_id -> Nullable<Int4>,
+        starting -> Int4,
+        remaining -> Int4,
+        price -> Nullable<Int4>,
+    }
+}
+
 diesel::table! {
     tokens (id) {
         id -> Int4,
@@ -198,40 +470,125 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    wishlist_bundles (id) {
+        id -> Int4,
+        user_id -> Int4,
+        bundle_id -> Int4,
+        quantity -> Int4,
+    }
+}
+
+diesel::table! {
+    wishlist_goods (id) {
+        id -> Int4,
+        user_id -> Int4,
+        goods_id -> Int4,
+        quantity -> Int4,
+    }
+}
+
+diesel::joinable!(character_aliases -> characters (character_id));
 diesel::joinable!(characters -> refs (reference_id));
 diesel::joinable!(circle_artists -> artists (artist_id));
 diesel::joinable!(circle_artists -> circles (circle_id));
 diesel::joinable!(circle_bundles -> bundles (bundle_id));
 diesel::joinable!(circle_bundles -> circles (circle_id));
+diesel::joinable!(circle_events -> circles (circle_id));
+diesel::joinable!(circle_events -> events (event_id));
 diesel::joinable!(circle_goods -> circles (circle_id));
 diesel::joinable!(circle_goods -> goods (goods_id));
 diesel::joinable!(circle_links -> circles (circle_id));
 diesel::joinable!(circle_links -> links (link_id));
+diesel::joinable!(collection_goods -> collections (collection_id));
+diesel::joinable!(collection_goods -> goods (goods_id));
+diesel::joinable!(collections -> users (user_id));
 diesel::joinable!(goods -> categories (category_id));
 diesel::joinable!(goods_character -> characters (character_id));
 diesel::joinable!(goods_character -> goods (goods_id));
 diesel::joinable!(goods_in_bundle -> bundles (bundle_id));
 diesel::joinable!(goods_in_bundle -> goods (goods_id));
+diesel::joinable!(order_form_items -> bundles (bundle_id));
+diesel::joinable!(order_form_items -> goods (goods_id));
+diesel::joinable!(order_form_items -> order_forms (order_form_id));
+diesel::joinable!(order_forms -> circles (circle_id));
+diesel::joinable!(order_items -> order_form_items (order_form_item_id));
+diesel::joinable!(order_items -> orders (order_id));
+diesel::joinable!(orders -> order_forms (order_form_id));
+diesel::joinable!(orders -> users (user_id));
+diesel::joinable!(prepayment_items -> bundles (bundle_id));
+diesel::joinable!(prepayment_items -> goods (goods_id));
+diesel::joinable!(prepayment_items -> prepayments (prepayment_id));
+diesel::joinable!(prepayments -> circles (circle_id));
+diesel::joinable!(prepayments -> orders (order_id));
+diesel::joinable!(prepayments -> users (user_id));
+diesel::joinable!(reconciliation_deposits -> reconciliation_payments (payment_id));
+diesel::joinable!(reconciliation_deposits -> reconciliations (reconciliation_id));
+diesel::joinable!(reconciliation_payments -> reconciliations (reconciliation_id));
+diesel::joinable!(reconciliations -> circles (circle_id));
+diesel::joinable!(sale_items -> bundles (bundle_id));
+diesel::joinable!(sale_items -> goods (goods_id));
+diesel::joinable!(sale_items -> sales (sale_id));
+diesel::joinable!(sales -> circles (circle_id));
+diesel::joinable!(sales -> users (user_id));
+diesel::joinable!(survey_items -> bundles (bundle_id));
+diesel::joinable!(survey_items -> goods (goods_id));
+diesel::joinable!(survey_items -> surveys (survey_id));
+diesel::joinable!(survey_responses -> survey_items (survey_item_id));
+diesel::joinable!(survey_responses -> users (user_id));
+diesel::joinable!(surveys -> circles (circle_id));
+diesel::joinable!(tallies -> circles (circle_id));
+diesel::joinable!(tallies -> events (event_id));
+diesel::joinable!(tally_items -> bundles (bundle_id));
+diesel::joinable!(tally_items -> goods (goods_id));
+diesel::joinable!(tally_items -> tallies (tally_id));
 diesel::joinable!(tokens -> users (user_id));
 diesel::joinable!(user_circles -> circles (circle_id));
 diesel::joinable!(user_circles -> users (user_id));
+diesel::joinable!(wishlist_bundles -> bundles (bundle_id));
+diesel::joinable!(wishlist_bundles -> users (user_id));
+diesel::joinable!(wishlist_goods -> goods (goods_id));
+diesel::joinable!(wishlist_goods -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     artists,
     bundles,
     categories,
+    character_aliases,
     characters,
     circle_artists,
     circle_bundles,
+    circle_events,
     circle_goods,
     circle_links,
     circles,
+    collection_goods,
+    collections,
+    events,
     goods,
     goods_character,
     goods_in_bundle,
     links,
+    order_form_items,
+    order_forms,
+    order_items,
+    orders,
+    prepayment_items,
+    prepayments,
+    reconciliation_deposits,
+    reconciliation_payments,
+    reconciliations,
     refs,
+    sale_items,
+    sales,
+    survey_items,
+    survey_responses,
+    surveys,
+    tallies,
+    tally_items,
     tokens,
     user_circles,
     users,
+    wishlist_bundles,
+    wishlist_goods,
 );
@@ -0,0 +1,200 @@
+//! Database fixtures for tests. Every connection handed out here runs inside a
+//! transaction that is never committed, so tests leave the database as they
+//! found it. Tests that need one are ignored by default; run them with
+//! `cargo test -- --ignored` against a migrated database.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use diesel::connection::{Instrumentation, InstrumentationEvent};
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, CustomizeConnection, Pool, PooledConnection};
+
+use crate::models::{AuthenticatedUser, BundleTypeEnum, RoleTypeEnum};
+use crate::DbPool;
+
+pub type TestConnection = PooledConnection<ConnectionManager<PgConnection>>;
+
+#[derive(Debug)]
+struct TestTransaction;
+
+impl CustomizeConnection<PgConnection, diesel::r2d2::Error> for TestTransaction {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), diesel::r2d2::Error> {
+        conn.begin_test_transaction()
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+/// A pool of a single connection, for calling routes directly. Drop any
+/// connection taken from it before calling a route, which takes its own.
+pub fn test_pool() -> DbPool {
+    dotenvy::dotenv().ok();
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+
+    Pool::builder()
+        .max_size(1)
+        .connection_customizer(Box::new(TestTransaction))
+        .build(ConnectionManager::<PgConnection>::new(database_url))
+        .expect("Failed to create pool")
+}
+
+pub fn test_connection() -> TestConnection {
+    test_pool()
+        .get()
+        .expect("Failed to get database connection")
+}
+
+/// A moderator, who may change the goods and bundles of every circle.
+pub fn moderator() -> AuthenticatedUser {
+    AuthenticatedUser {
+        id: 0,
+        handle: "fixture".into(),
+        nickname: "Fixture".into(),
+        twitter_id: None,
+        email: "fixture@example.com".into(),
+        role: RoleTypeEnum::moderator,
+        circles: vec![],
+    }
+}
+
+/// Counts the statements `conn` sends from now on.
+pub fn count_queries(conn: &mut TestConnection) -> Arc<AtomicUsize> {
+    let queries = Arc::new(AtomicUsize::new(0));
+    let counter = queries.clone();
+
+    conn.set_instrumentation(move |event: InstrumentationEvent<'_>| {
+        if let InstrumentationEvent::StartQuery { .. } = event {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+
+    queries
+}
+
+/// Stops counting statements on `conn`.
+pub fn stop_counting(conn: &mut TestConnection) {
+    conn.set_instrumentation(None::<Box<dyn Instrumentation>>);
+}
+
+pub fn insert_circle(conn: &mut TestConnection) -> i32 {
+    use crate::schema::circles;
+
+    diesel::insert_into(circles::table)
+        .values(circles::name.eq("Fixture circle"))
+        .returning(circles::id)
+        .get_result(conn)
+        .expect("Failed to insert circle")
+}
+
+pub fn insert_category(conn: &mut TestConnection) -> i32 {
+    use crate::schema::categories;
+
+    diesel::insert_into(categories::table)
+        .values(categories::name.eq("Fixture category"))
+        .returning(categories::id)
+        .get_result(conn)
+        .expect("Failed to insert category")
+}
+
+/// Inserts a `select` bundle of one item owned by `circle_id`.
+pub fn insert_bundle(conn: &mut TestConnection, circle_id: i32) -> i32 {
+    use crate::schema::bundles;
+    use crate::schema::circle_bundles;
+
+    let bundle_id = diesel::insert_into(bundles::table)
+        .values((
+            bundles::name.eq("Fixture bundle"),
+            bundles::type_.eq(BundleTypeEnum::select),
+            bundles::count.eq(1),
+        ))
+        .returning(bundles::id)
+        .get_result(conn)
+        .expect("Failed to insert bundle");
+
+    diesel::insert_into(circle_bundles::table)
+        .values((
+            circle_bundles::circle_id.eq(circle_id),
+            circle_bundles::bundle_id.eq(bundle_id),
+        ))
+        .execute(conn)
+        .expect("Failed to insert circle bundle");
+
+    bundle_id
+}
+
+/// Inserts a character of a fresh reference, with one alias.
+pub fn insert_character(conn: &mut TestConnection) -> i32 {
+    use crate::schema::characters;
+    use crate::schema::refs;
+
+    let reference_id = diesel::insert_into(refs::table)
+        .values(refs::name.eq("Fixture reference"))
+        .returning(refs::id)
+        .get_result::<i32>(conn)
+        .expect("Failed to insert reference");
+
+    let character_id = diesel::insert_into(characters::table)
+        .values((
+            characters::name.eq("Fixture character"),
+            characters::reference_id.eq(reference_id),
+        ))
+        .returning(characters::id)
+        .get_result(conn)
+        .expect("Failed to insert character");
+
+    diesel::sql_query(
+        "INSERT INTO character_aliases (character_id, language, kind, name) \
+         VALUES ($1, 'en', 'nickname', 'Fixture alias')",
+    )
+    .bind::<diesel::sql_types::Integer, _>(character_id)
+    .execute(conn)
+    .expect("Failed to insert alias");
+
+    character_id
+}
+
+/// Inserts `count` goods in `category_id`, each sold by `circle_id` and
+/// showing `character_id`, and returns their ids in order.
+pub fn insert_goods(
+    conn: &mut TestConnection,
+    count: usize,
+    circle_id: i32,
+    category_id: i32,
+    character_id: i32,
+) -> Vec<i32> {
+    use crate::schema::circle_goods;
+    use crate::schema::goods;
+    use crate::schema::goods_character;
+
+    (0..count)
+        .map(|index| {
+            let goods_id = diesel::insert_into(goods::table)
+                .values((
+                    goods::name.eq(format!("Fixture goods {}", index)),
+                    goods::price.eq(1000),
+                    goods::category_id.eq(category_id),
+                ))
+                .returning(goods::id)
+                .get_result(conn)
+                .expect("Failed to insert goods");
+
+            diesel::insert_into(circle_goods::table)
+                .values((
+                    circle_goods::circle_id.eq(circle_id),
+                    circle_goods::goods_id.eq(goods_id),
+                ))
+                .execute(conn)
+                .expect("Failed to insert circle goods");
+
+            diesel::insert_into(goods_character::table)
+                .values((
+                    goods_character::goods_id.eq(goods_id),
+                    goods_character::character_id.eq(character_id),
+                ))
+                .execute(conn)
+                .expect("Failed to insert goods character");
+
+            goods_id
+        })
+        .collect()
+}
@@ -0,0 +1,325 @@
+use crate::utils::csv;
+
+/// Header names recognised for each column, most specific first. Headers are
+/// compared after [`header_key`], so spacing, brackets and a trailing `원`
+/// do not matter.
+const NAME_HEADERS: &[&str] = &[
+    "입금자명",
+    "입금자",
+    "보낸분",
+    "보낸사람",
+    "depositorname",
+    "depositor",
+    "name",
+    "이름",
+    "기재내용",
+    "적요",
+    "내용",
+];
+const AMOUNT_HEADERS: &[&str] = &[
+    "입금액",
+    "입금금액",
+    "맡기신금액",
+    "입금",
+    "amount",
+    "deposit",
+    "금액",
+    "거래금액",
+];
+const DATE_HEADERS: &[&str] = &[
+    "거래일시",
+    "거래일자",
+    "거래일",
+    "일시",
+    "날짜",
+    "datetime",
+    "date",
+];
+const NOTE_HEADERS: &[&str] = &["note", "memo", "reference", "비고", "메모", "주문번호"];
+
+/// Bank exports put the account summary above the table, so the header is
+/// searched for among this many leading records.
+const HEADER_SEARCH_DEPTH: usize = 20;
+
+/// One row of an expected payment list or of a bank statement.
+pub struct Transfer {
+    pub line: i32,
+    pub depositor_name: String,
+    pub amount: i32,
+    pub deposited_at: Option<String>,
+    pub note: Option<String>,
+}
+
+/// How a deposit was matched against the expected payments, by index into
+/// the payment list.
+pub enum Match {
+    Exact(usize),
+    Fuzzy(usize),
+    Ambiguous(Vec<usize>),
+    Unmatched,
+}
+
+fn header_key(header: &str) -> String {
+    let key = header
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+
+    match key.strip_suffix('원').or_else(|| key.strip_suffix("krw")) {
+        Some(stripped) if !stripped.is_empty() => stripped.to_string(),
+        _ => key,
+    }
+}
+
+fn find_column(header: &[String], names: &[&str]) -> Option<usize> {
+    let keys = header.iter().map(|h| header_key(h)).collect::<Vec<_>>();
+
+    names
+        .iter()
+        .find_map(|name| keys.iter().position(|key| key == name))
+}
+
+/// Reads `10,000`, `₩10000원` or `10000.00` as a whole amount.
+fn parse_amount(cell: &str) -> Option<i32> {
+    let cell = cell
+        .chars()
+        .filter(|c| !matches!(c, ',' | '원' | '₩' | '+') && !c.is_whitespace())
+        .collect::<String>();
+
+    let whole = match cell.split_once('.') {
+        Some((whole, fraction)) if fraction.chars().all(|c| c == '0') => whole,
+        Some(_) => return None,
+        None => &cell,
+    };
+
+    whole.parse::<i32>().ok()
+}
+
+/// Parses a CSV of transfers. The header row needs a depositor name and an
+/// amount column; a date and a note column are picked up when present.
+///
+/// Rows without a positive amount are skipped, since statements list
+/// withdrawals in the same table, as are rows without a name such as
+/// running totals.
+pub fn parse_transfers(input: &str) -> Result<Vec<Transfer>, String> {
+    let records = csv::parse(input).map_err(|_| "CSV has an unterminated quote".to_string())?;
+
+    let (header_index, name_column, amount_column) = records
+        .iter()
+        .take(HEADER_SEARCH_DEPTH)
+        .enumerate()
+        .find_map(|(index, record)| {
+            Some((
+                index,
+                find_column(record, NAME_HEADERS)?,
+                find_column(record, AMOUNT_HEADERS)?,
+            ))
+        })
+        .ok_or_else(|| "CSV has no depositor name and amount columns".to_string())?;
+
+    let date_column = find_column(&records[header_index], DATE_HEADERS);
+    let note_column = find_column(&records[header_index], NOTE_HEADERS);
+
+    let mut transfers = Vec::new();
+
+    for (index, record) in records.iter().enumerate().skip(header_index + 1) {
+        let line = index as i32 + 1;
+        let cell = |column: Option<usize>| {
+            column
+                .and_then(|column| record.get(column))
+                .map(|cell| cell.trim())
+                .filter(|cell| !cell.is_empty())
+        };
+
+        let Some(amount) = cell(Some(amount_column)) else {
+            continue;
+        };
+        let amount =
+            parse_amount(amount).ok_or_else(|| format!("Invalid amount on line {}", line))?;
+
+        let Some(depositor_name) = cell(Some(name_column)) else {
+            continue;
+        };
+
+        if amount <= 0 {
+            continue;
+        }
+
+        transfers.push(Transfer {
+            line,
+            depositor_name: depositor_name.to_string(),
+            amount,
+            deposited_at: cell(date_column).map(str::to_string),
+            note: cell(note_column).map(str::to_string),
+        });
+    }
+
+    Ok(transfers)
+}
+
+type NameTest = fn(&[char], &[char]) -> bool;
+
+fn name_key(name: &str) -> Vec<char> {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+
+        for (j, cb) in b.iter().enumerate() {
+            current[j + 1] = (previous[j] + usize::from(ca != cb))
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Whether two depositor names plausibly belong to the same person. Banks
+/// cut long names short, so a prefix of two or more characters counts, as
+/// does a single typo in names of three or more.
+fn similar(a: &[char], b: &[char]) -> bool {
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+
+    (short.len() >= 2 && long.starts_with(short))
+        || (short.len() >= 3 && edit_distance(short, long) <= 1)
+}
+
+/// Matches every deposit to at most one expected payment, and every payment
+/// to at most one deposit.
+///
+/// Deposits are first paired on identical name and amount, then on a similar
+/// name and identical amount. Payments alike in name and amount go to such
+/// deposits in order. A deposit with several equally good payments under
+/// different names, or whose name only matches payments of another amount,
+/// is left ambiguous with those payments as candidates.
+pub fn reconcile(payments: &[Transfer], deposits: &[Transfer]) -> Vec<Match> {
+    let payment_keys = payments
+        .iter()
+        .map(|payment| name_key(&payment.depositor_name))
+        .collect::<Vec<_>>();
+    let deposit_keys = deposits
+        .iter()
+        .map(|deposit| name_key(&deposit.depositor_name))
+        .collect::<Vec<_>>();
+
+    let mut taken = vec![false; payments.len()];
+    let mut matches = deposits
+        .iter()
+        .map(|_| None)
+        .collect::<Vec<Option<Match>>>();
+
+    let passes: [NameTest; 2] = [|a, b| a == b, similar];
+
+    for (pass, same_name) in passes.iter().enumerate() {
+        for (index, deposit) in deposits.iter().enumerate() {
+            if matches[index].is_some() {
+                continue;
+            }
+
+            let candidates = (0..payments.len())
+                .filter(|&i| {
+                    !taken[i]
+                        && payments[i].amount == deposit.amount
+                        && same_name(&payment_keys[i], &deposit_keys[index])
+                })
+                .collect::<Vec<_>>();
+
+            // Payments with the same name and amount are interchangeable, so
+            // when every candidate is alike the first one left is taken.
+            let identical = candidates
+                .iter()
+                .all(|&i| payment_keys[i] == payment_keys[candidates[0]]);
+
+            matches[index] = match candidates[..] {
+                [] => None,
+                [i, ..] if identical => {
+                    taken[i] = true;
+                    Some(if pass == 0 {
+                        Match::Exact(i)
+                    } else {
+                        Match::Fuzzy(i)
+                    })
+                }
+                _ => Some(Match::Ambiguous(candidates)),
+            };
+        }
+    }
+
+    matches
+        .into_iter()
+        .zip(deposit_keys)
+        .map(|(result, key)| {
+            result.unwrap_or_else(|| {
+                let candidates = (0..payments.len())
+                    .filter(|&i| !taken[i] && similar(&payment_keys[i], &key))
+                    .collect::<Vec<_>>();
+
+                if candidates.is_empty() {
+                    Match::Unmatched
+                } else {
+                    Match::Ambiguous(candidates)
+                }
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(depositor_name: &str, amount: i32) -> Transfer {
+        Transfer {
+            line: 1,
+            depositor_name: depositor_name.to_string(),
+            amount,
+            deposited_at: None,
+            note: None,
+        }
+    }
+
+    fn indices(matches: &[Match]) -> Vec<String> {
+        matches
+            .iter()
+            .map(|result| match result {
+                Match::Exact(i) => format!("exact {}", i),
+                Match::Fuzzy(i) => format!("fuzzy {}", i),
+                Match::Ambiguous(candidates) => format!("ambiguous {:?}", candidates),
+                Match::Unmatched => "unmatched".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identical_payments_are_matched_in_order() {
+        let payments = [transfer("김철수", 10000), transfer("김철수", 10000)];
+        let deposits = [transfer("김철수", 10000), transfer("김 철수", 10000)];
+
+        assert_eq!(
+            indices(&reconcile(&payments, &deposits)),
+            ["exact 0", "exact 1"]
+        );
+    }
+
+    #[test]
+    fn similar_payments_under_different_names_stay_ambiguous() {
+        let payments = [transfer("김철수", 10000), transfer("김철민", 10000)];
+        let deposits = [transfer("김철", 10000)];
+
+        assert_eq!(
+            indices(&reconcile(&payments, &deposits)),
+            ["ambiguous [0, 1]"]
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Longest hall or block name, in characters, as stored in `circles`.
+const MAX_NAME_LENGTH: usize = 16;
+/// Most digits in a space number, which keeps it well within an `int`.
+const MAX_SPACE_DIGITS: usize = 9;
+
+/// A booth location such as `A-12b` or `1홀 가-3ab`, split into the parts
+/// that make up its physical position on the floor.
+///
+/// The derived ordering follows the floor layout: hall, then block, then
+/// space number, then half-space suffix.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Booth {
+    pub hall: Option<String>,
+    pub block: String,
+    pub space: i32,
+    pub half: Option<String>,
+}
+
+impl FromStr for Booth {
+    type Err = ();
+
+    /// Accepts `[<hall> ]<block>[-]<space>[a|b|ab]`, case-insensitively.
+    ///
+    /// The backfill in the `add_booth_to_circles` migration mirrors these
+    /// rules; keep the two in step.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = s.split_whitespace().collect::<Vec<_>>();
+
+        let (hall, rest) = match tokens[..] {
+            [rest] => (None, rest),
+            [hall, rest] if hall.chars().all(char::is_alphanumeric) => {
+                (Some(hall.to_uppercase()), rest)
+            }
+            _ => return Err(()),
+        };
+
+        if hall
+            .as_ref()
+            .is_some_and(|hall| hall.chars().count() > MAX_NAME_LENGTH)
+        {
+            return Err(());
+        }
+
+        let space_start = rest.find(|c: char| c.is_ascii_digit()).ok_or(())?;
+        let block = rest[..space_start].strip_suffix('-').unwrap_or(&rest[..space_start]);
+
+        if block.is_empty()
+            || block.chars().count() > MAX_NAME_LENGTH
+            || !block.chars().all(char::is_alphabetic)
+        {
+            return Err(());
+        }
+
+        let rest = &rest[space_start..];
+        let half_start = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+
+        if half_start > MAX_SPACE_DIGITS {
+            return Err(());
+        }
+
+        let space = rest[..half_start].parse::<i32>().map_err(|_| ())?;
+        let half = rest[half_start..].to_lowercase();
+
+        if space <= 0 {
+            return Err(());
+        }
+
+        let half = match half.as_str() {
+            "" => None,
+            "a" | "b" | "ab" => Some(half),
+            _ => return Err(()),
+        };
+
+        Ok(Booth {
+            hall,
+            block: block.to_uppercase(),
+            space,
+            half,
+        })
+    }
+}
+
+impl fmt::Display for Booth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(hall) = &self.hall {
+            write!(f, "{} ", hall)?;
+        }
+
+        write!(
+            f,
+            "{}-{}{}",
+            self.block,
+            self.space,
+            self.half.as_deref().unwrap_or("")
+        )
+    }
+}
+
+/// Orders stops in a serpentine walk: hall by hall and block by block,
+/// alternating the direction along each block so that consecutive stops stay
+/// next to each other. With `reverse` set the whole walk runs backwards,
+/// starting from the far end of the last block.
+pub fn serpentine<T>(mut stops: Vec<(Booth, T)>, reverse: bool) -> Vec<(Booth, T)> {
+    stops.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut blocks: Vec<Vec<(Booth, T)>> = vec![];
+
+    for stop in stops {
+        match blocks.last_mut() {
+            Some(block)
+                if block[0].0.hall == stop.0.hall && block[0].0.block == stop.0.block =>
+            {
+                block.push(stop)
+            }
+            _ => blocks.push(vec![stop]),
+        }
+    }
+
+    if reverse {
+        blocks.reverse();
+    }
+
+    blocks
+        .into_iter()
+        .enumerate()
+        .flat_map(|(i, mut block)| {
+            if (i % 2 == 1) != reverse {
+                block.reverse();
+            }
+            block
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn booth(hall: Option<&str>, block: &str, space: i32, half: Option<&str>) -> Booth {
+        Booth {
+            hall: hall.map(String::from),
+            block: block.into(),
+            space,
+            half: half.map(String::from),
+        }
+    }
+
+    #[test]
+    fn parses_booth_codes() {
+        assert_eq!("A-12b".parse(), Ok(booth(None, "A", 12, Some("b"))));
+        assert_eq!(
+            "1홀 가-3ab".parse(),
+            Ok(booth(Some("1홀"), "가", 3, Some("ab")))
+        );
+        assert_eq!("A12".parse(), Ok(booth(None, "A", 12, None)));
+    }
+
+    #[test]
+    fn folds_case() {
+        assert_eq!(
+            " east  ab-7AB ".parse(),
+            Ok(booth(Some("EAST"), "AB", 7, Some("ab")))
+        );
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_booth_code() {
+        for location in [
+            "",
+            "1 2 A-1",
+            "1! A-1",
+            &format!("{} A-1", "H".repeat(17)),
+            "A-",
+            "-12",
+            "A!-1",
+            "A--1",
+            &format!("{}-1", "A".repeat(17)),
+            "A-0",
+            "A-1234567890",
+            "A-1c",
+            "A-1a2",
+        ] {
+            assert_eq!(location.parse::<Booth>(), Err(()), "{:?}", location);
+        }
+    }
+
+    #[test]
+    fn displays_the_normal_form() {
+        let booth = "1홀 가3AB".parse::<Booth>().unwrap();
+
+        assert_eq!(booth.to_string(), "1홀 가-3ab");
+        assert_eq!(booth.to_string().parse(), Ok(booth));
+        assert_eq!("a12".parse::<Booth>().unwrap().to_string(), "A-12");
+    }
+
+    #[test]
+    fn orders_by_hall_block_space_and_half() {
+        let mut booths = ["1 A-1", "B-1", "A-10", "A-2b", "A-2", "A-2a"]
+            .map(|location| location.parse::<Booth>().unwrap());
+        booths.sort();
+
+        assert_eq!(
+            booths.map(|booth| booth.to_string()),
+            ["A-2", "A-2a", "A-2b", "A-10", "B-1", "1 A-1"]
+        );
+    }
+
+    fn walk(locations: &[&str], reverse: bool) -> Vec<String> {
+        let stops = locations
+            .iter()
+            .map(|location| (location.parse::<Booth>().unwrap(), ()))
+            .collect();
+
+        serpentine(stops, reverse)
+            .into_iter()
+            .map(|(booth, ())| booth.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn serpentine_alternates_direction_per_block() {
+        assert_eq!(
+            walk(&["B-2", "A-2", "C-1", "B-1", "A-1", "C-2"], false),
+            ["A-1", "A-2", "B-2", "B-1", "C-1", "C-2"]
+        );
+    }
+
+    #[test]
+    fn serpentine_runs_backwards_when_reversed() {
+        assert_eq!(
+            walk(&["B-2", "A-2", "C-1", "B-1", "A-1", "C-2"], true),
+            ["C-2", "C-1", "B-1", "B-2", "A-2", "A-1"]
+        );
+    }
+
+    #[test]
+    fn serpentine_treats_each_hall_as_its_own_blocks() {
+        assert_eq!(
+            walk(&["2 A-1", "1 A-2", "1 A-1", "2 A-2"], false),
+            ["1 A-1", "1 A-2", "2 A-2", "2 A-1"]
+        );
+    }
+}
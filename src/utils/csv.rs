@@ -0,0 +1,134 @@
+use std::borrow::Cow;
+
+/// Quotes `field` as RFC 4180 asks when it contains a separator, a quote or a
+/// line break.
+pub fn escape(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\r', '\n']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Prefixes free text that a spreadsheet would read as a formula with `'`,
+/// so that opening an export never runs what a user typed. Use it on text
+/// fields only; numbers keep their sign.
+pub fn text(field: String) -> String {
+    if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", field)
+    } else {
+        field
+    }
+}
+
+/// Formats one CSV record, including the trailing line break.
+pub fn row<I, S>(fields: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut row = fields
+        .into_iter()
+        .map(|field| escape(field.as_ref()).into_owned())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    row.push_str("\r\n");
+    row
+}
+
+/// Splits RFC 4180 text into records. Quoted fields may contain separators,
+/// doubled quotes and line breaks; a leading byte order mark is ignored.
+/// Fails on a quote that is never closed.
+pub fn parse(input: &str) -> Result<Vec<Vec<String>>, ()> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = input.trim_start_matches('\u{feff}').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\r' | '\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(());
+    }
+
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_only_fields_that_need_it() {
+        assert_eq!(escape("plain"), "plain");
+        assert_eq!(escape("a,b"), "\"a,b\"");
+        assert_eq!(escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn defuses_formulas_in_text() {
+        for field in ["=1+1", "+1", "-1", "@SUM(A1)", "\tx", "\rx"] {
+            assert_eq!(text(field.into()), format!("'{}", field));
+        }
+
+        assert_eq!(text("1+1=2".into()), "1+1=2");
+        assert_eq!(
+            row([text("=HYPERLINK(\"x\")".into())]),
+            "\"'=HYPERLINK(\"\"x\"\")\"\r\n"
+        );
+    }
+
+    #[test]
+    fn parses_what_row_writes() {
+        let fields = ["plain", "a,b", "say \"hi\"", "two\r\nlines", ""];
+
+        assert_eq!(
+            parse(&row(fields)),
+            Ok(vec![fields.map(String::from).to_vec()])
+        );
+    }
+
+    #[test]
+    fn parses_records() {
+        assert_eq!(
+            parse("\u{feff}a,\"b,c\"\r\n\"d\"\"e\",\n\nf"),
+            Ok(vec![
+                vec!["a".to_string(), "b,c".to_string()],
+                vec!["d\"e".to_string(), String::new()],
+                vec![String::new()],
+                vec!["f".to_string()],
+            ])
+        );
+        assert_eq!(parse(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn rejects_an_unterminated_quote() {
+        assert_eq!(parse("a,\"b\r\n"), Err(()));
+    }
+}
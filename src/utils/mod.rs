@@ -1,3 +1,10 @@
+pub(crate) mod bank;
+pub(crate) mod booth;
+pub(crate) mod csv;
+pub(crate) mod odds;
+pub(crate) mod pagination;
+pub(crate) mod search;
+
 pub(crate) mod strings {
     use rand_core::RngCore;
 
@@ -8,7 +15,7 @@ pub(crate) mod strings {
                 let v = rand_core::OsRng.next_u32() % 62;
                 if v <= 9 {
                     ('0' as u32) + v
-                } else if 10 <= v && v <= 35 {
+                } else if (10..=35).contains(&v) {
                     ('A' as u32) + v - 10
                 } else {
                     ('a' as u32) + v - 36
@@ -16,12 +23,33 @@ pub(crate) mod strings {
             })
             .map(char::from_u32)
             .scan(Some(""), |acc, x| match acc {
-                Some(acc) => match x {
-                    Some(x) => Some(acc.to_string() + &x.to_string()),
-                    None => None,
-                },
+                Some(acc) => x.map(|x| acc.to_string() + &x.to_string()),
                 None => None,
             })
             .collect()
     }
+
+    /// Digits and capitals without `0`, `1`, `I` and `O`, which are easily
+    /// confused when read off a phone screen. Exactly 32 symbols, so picking
+    /// one from a random `u32` is unbiased.
+    const CODE_ALPHABET: &[u8; 32] = b"23456789ABCDEFGHJKLMNPQRSTUVWXYZ";
+
+    /// Generates a code of `len` symbols that is easy to read out and type.
+    pub fn generate_code(len: usize) -> String {
+        (0..len)
+            .map(|_| CODE_ALPHABET[(rand_core::OsRng.next_u32() % 32) as usize] as char)
+            .collect()
+    }
+
+    /// Escapes `%`, `_` and `\` so that `s` matches literally inside a
+    /// `LIKE` pattern.
+    pub fn escape_like(s: &str) -> String {
+        s.chars().fold(String::with_capacity(s.len()), |mut acc, c| {
+            if matches!(c, '%' | '_' | '\\') {
+                acc.push('\\');
+            }
+            acc.push(c);
+            acc
+        })
+    }
 }
@@ -0,0 +1,235 @@
+use rand_core::RngCore;
+
+/// Up to this many wanted goods the expectation is worked out exactly by
+/// inclusion–exclusion over every subset. Beyond that the alternating sum
+/// loses too much precision, and it is estimated by simulation instead.
+const EXACT_LIMIT: usize = 12;
+
+const TRIALS: u32 = 2_000;
+
+/// Items drawn across all trials of one simulation. Running out means the
+/// weights are too lopsided for an estimate to mean anything, and keeps a
+/// single request from holding a core for long.
+const MAX_DRAWS: u64 = 20_000_000;
+
+/// Largest pack the odds are worked out for.
+pub const MAX_PER_PACK: u32 = 100;
+
+pub enum Method {
+    Exact,
+    Simulation { trials: u32 },
+}
+
+/// Expected number of packs to draw until every `wanted` goods has come up
+/// at least once.
+///
+/// Each of the `per_pack` items in a pack is drawn independently, goods `i`
+/// with probability `weights[i]` over the total weight. Returns `None` when
+/// the simulation gives up on the set. `per_pack` is at most
+/// [`MAX_PER_PACK`].
+pub fn expected_packs(weights: &[u32], wanted: &[usize], per_pack: u32) -> Option<(f64, Method)> {
+    if per_pack == 0 || per_pack > MAX_PER_PACK {
+        return None;
+    }
+
+    let total = weights.iter().map(|&weight| f64::from(weight)).sum::<f64>();
+    let probabilities = wanted
+        .iter()
+        .map(|&i| f64::from(weights[i]) / total)
+        .collect::<Vec<_>>();
+
+    if wanted.len() <= EXACT_LIMIT {
+        Some((exact(&probabilities, per_pack), Method::Exact))
+    } else {
+        simulate(weights, wanted, per_pack, Xorshift::new())
+            .map(|packs| (packs, Method::Simulation { trials: TRIALS }))
+    }
+}
+
+/// The set is still incomplete after `k` packs when some subset `J` of it
+/// has not come up at all, which happens with probability
+/// `(1 - P(J))^(per_pack * k)`. Summing over `k` and `J` with alternating
+/// signs gives `Σ (-1)^(|J|+1) / (1 - (1 - P(J))^per_pack)`.
+fn exact(probabilities: &[f64], per_pack: u32) -> f64 {
+    let mut subset_probability = vec![0.0; 1 << probabilities.len()];
+    let mut expected = 0.0;
+
+    for mask in 1..subset_probability.len() {
+        let lowest = mask.trailing_zeros() as usize;
+        let probability = subset_probability[mask & (mask - 1)] + probabilities[lowest];
+        subset_probability[mask] = probability;
+
+        let term = 1.0 / (1.0 - (1.0 - probability).powi(per_pack as i32));
+
+        if mask.count_ones() % 2 == 1 {
+            expected += term;
+        } else {
+            expected -= term;
+        }
+    }
+
+    expected
+}
+
+/// xorshift64*, plenty for a simulation and seeded once from the OS.
+struct Xorshift(u64);
+
+impl Xorshift {
+    fn new() -> Self {
+        Xorshift(rand_core::OsRng.next_u64() | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+fn simulate(weights: &[u32], wanted: &[usize], per_pack: u32, mut rng: Xorshift) -> Option<f64> {
+    let cumulative = weights
+        .iter()
+        .scan(0u64, |sum, &weight| {
+            *sum += u64::from(weight);
+            Some(*sum)
+        })
+        .collect::<Vec<_>>();
+    let total = *cumulative.last()?;
+
+    let mut packs = 0;
+    let mut draws = 0;
+
+    for _ in 0..TRIALS {
+        let mut missing = vec![false; weights.len()];
+        let mut left = wanted.len();
+
+        for &i in wanted {
+            missing[i] = true;
+        }
+
+        let mut trial_packs = 0u64;
+
+        while left > 0 {
+            draws += u64::from(per_pack);
+
+            if draws > MAX_DRAWS {
+                return None;
+            }
+
+            trial_packs += 1;
+
+            for _ in 0..per_pack {
+                let roll = rng.next_u64() % total;
+                let drawn = cumulative.partition_point(|&sum| sum <= roll);
+
+                if missing[drawn] {
+                    missing[drawn] = false;
+                    left -= 1;
+                }
+            }
+        }
+
+        packs += trial_packs;
+    }
+
+    Some(packs as f64 / f64::from(TRIALS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fixed seed, so the estimates checked below are the same every run.
+    fn seeded() -> Xorshift {
+        Xorshift(0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn harmonic(n: u32) -> f64 {
+        (1..=n).map(|k| 1.0 / f64::from(k)).sum()
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= expected * tolerance,
+            "expected {} within {}%, got {}",
+            expected,
+            tolerance * 100.0,
+            actual
+        );
+    }
+
+    #[test]
+    fn exact_matches_coupon_collector_for_equal_weights() {
+        for n in 1..=EXACT_LIMIT as u32 {
+            let probabilities = vec![1.0 / f64::from(n); n as usize];
+
+            assert_close(exact(&probabilities, 1), f64::from(n) * harmonic(n), 1e-9);
+        }
+    }
+
+    #[test]
+    fn exact_handles_unequal_weights() {
+        // 1/p1 + 1/p2 - 1/(p1 + p2)
+        assert_close(exact(&[0.25, 0.75], 1), 4.0 + 4.0 / 3.0 - 1.0, 1e-9);
+    }
+
+    #[test]
+    fn exact_counts_packs_not_items() {
+        // The first pack of two completes a pair half the time; otherwise
+        // every further pack does with probability 3/4.
+        assert_close(exact(&[0.5, 0.5], 2), 1.0 + 0.5 * 4.0 / 3.0, 1e-9);
+    }
+
+    #[test]
+    fn exact_covers_a_subset() {
+        // Two specific goods out of four: 4 * H(2)
+        assert_close(exact(&[0.25, 0.25], 1), 4.0 * harmonic(2), 1e-9);
+    }
+
+    #[test]
+    fn simulate_approximates_coupon_collector() {
+        let weights = vec![1; 20];
+        let wanted = (0..20).collect::<Vec<_>>();
+
+        let packs = simulate(&weights, &wanted, 1, seeded()).unwrap();
+
+        assert_close(packs, 20.0 * harmonic(20), 0.05);
+    }
+
+    #[test]
+    fn simulate_agrees_with_exact() {
+        let weights = [1, 2, 3, 4, 5, 6];
+        let wanted = [0, 2, 4];
+        let total = weights.iter().sum::<u32>() as f64;
+        let probabilities = wanted
+            .iter()
+            .map(|&i| f64::from(weights[i]) / total)
+            .collect::<Vec<_>>();
+
+        assert_close(
+            simulate(&weights, &wanted, 3, seeded()).unwrap(),
+            exact(&probabilities, 3),
+            0.05,
+        );
+    }
+
+    #[test]
+    fn simulate_gives_up_on_lopsided_weights() {
+        let mut weights = vec![1_000_000; EXACT_LIMIT + 1];
+        weights[0] = 1;
+        let wanted = (0..weights.len()).collect::<Vec<_>>();
+
+        assert!(simulate(&weights, &wanted, 1, seeded()).is_none());
+    }
+
+    #[test]
+    fn expected_packs_rejects_pack_sizes_out_of_range() {
+        assert!(expected_packs(&[1, 1], &[0, 1], 0).is_none());
+        assert!(expected_packs(&[1, 1], &[0, 1], MAX_PER_PACK + 1).is_none());
+        assert!(matches!(
+            expected_packs(&[1, 1], &[0, 1], MAX_PER_PACK),
+            Some((_, Method::Exact))
+        ));
+    }
+}
@@ -0,0 +1,426 @@
+use std::time::SystemTime;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use diesel::expression::{is_aggregate, AppearsOnTable, Expression, ValidGrouping};
+use diesel::pg::Pg;
+use diesel::query_builder::{AstPass, QueryFragment, QueryId};
+use diesel::sql_types::{Bool, Integer, Text, Timestamp};
+use diesel::QueryResult;
+use rocket::{http::Status, response::status::Custom, serde::json::Json};
+use serde::{Deserialize, Serialize};
+
+use crate::error_handler::{CustomError, ErrorInfo};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+/// Response envelope of the paginated list endpoints.
+#[derive(Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub total: i64,
+}
+
+impl<T> Page<T> {
+    /// Replaces the items, keeping the cursor and total.
+    pub fn try_map<U, E>(self, f: impl FnOnce(Vec<T>) -> Result<Vec<U>, E>) -> Result<Page<U>, E> {
+        Ok(Page {
+            items: f(self.items)?,
+            next_cursor: self.next_cursor,
+            total: self.total,
+        })
+    }
+}
+
+/// A value of a sort column, as carried in a cursor.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SortValue {
+    Int(i32),
+    Text(String),
+    Time(SystemTime),
+}
+
+impl From<i32> for SortValue {
+    fn from(value: i32) -> Self {
+        SortValue::Int(value)
+    }
+}
+
+impl From<String> for SortValue {
+    fn from(value: String) -> Self {
+        SortValue::Text(value)
+    }
+}
+
+impl From<SystemTime> for SortValue {
+    fn from(value: SystemTime) -> Self {
+        SortValue::Time(value)
+    }
+}
+
+/// Where the previous page ended: the sort it was read with, the sort
+/// columns of its last row and that row's id.
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    sort: String,
+    key: Vec<Option<SortValue>>,
+    id: i32,
+}
+
+/// The window of rows requested through `limit` and `cursor`.
+///
+/// Cursors are opaque to clients. They point just past the last row of the
+/// previous page, so rows added or removed in between do not shift the next
+/// page the way an offset would.
+pub struct PageRequest {
+    pub limit: i64,
+    sort: String,
+    after: Option<Keyset>,
+}
+
+impl PageRequest {
+    pub fn new(limit: Option<i64>, cursor: Option<&str>, sort: &Sort) -> Result<Self, CustomError> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT);
+
+        if !(1..=MAX_LIMIT).contains(&limit) {
+            return Err(Custom(
+                Status::BadRequest,
+                Json(ErrorInfo::new(format!(
+                    "limit must be between 1 and {}",
+                    MAX_LIMIT
+                ))),
+            ));
+        }
+
+        let after = match cursor {
+            Some(cursor) => {
+                let cursor = URL_SAFE_NO_PAD
+                    .decode(cursor)
+                    .ok()
+                    .and_then(|bytes| serde_json::from_slice::<Cursor>(&bytes).ok())
+                    .filter(|cursor| {
+                        cursor.sort == sort.to_string() && cursor.key.len() == sort.columns.len()
+                    })
+                    .ok_or_else(|| {
+                        Custom(
+                            Status::BadRequest,
+                            Json(ErrorInfo::new("Invalid cursor".into())),
+                        )
+                    })?;
+
+                Some(Keyset {
+                    columns: sort.columns,
+                    id_column: sort.id_column,
+                    descending: sort.descending,
+                    key: cursor.key,
+                    id: cursor.id,
+                })
+            }
+            None => None,
+        };
+
+        Ok(PageRequest {
+            limit,
+            sort: sort.to_string(),
+            after,
+        })
+    }
+
+    /// Filter for the rows after the cursor, if one was given.
+    pub fn after(&self) -> Option<Keyset> {
+        self.after.clone()
+    }
+
+    /// How many rows to load: one more than the page holds, which tells
+    /// whether another page follows.
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit + 1
+    }
+
+    /// Turns the rows loaded with `fetch_limit` into a page. `key` gives the
+    /// values of the sort columns and the id of a row.
+    pub fn page<T>(
+        &self,
+        mut items: Vec<T>,
+        total: i64,
+        key: impl Fn(&T) -> (Vec<Option<SortValue>>, i32),
+    ) -> Page<T> {
+        let has_more = items.len() as i64 > self.limit;
+        items.truncate(self.limit as usize);
+
+        let next_cursor = items.last().filter(|_| has_more).map(|last| {
+            let (key, id) = key(last);
+            let cursor = Cursor {
+                sort: self.sort.clone(),
+                key,
+                id,
+            };
+
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&cursor).expect("Failed to encode cursor"))
+        });
+
+        Page {
+            items,
+            next_cursor,
+            total,
+        }
+    }
+}
+
+/// A `sort` parameter such as `name` or `-price` (descending).
+///
+/// Rows are ordered by the key's columns and then by id, all in the same
+/// direction, with nulls last when ascending and first when descending as
+/// PostgreSQL does by default.
+pub struct Sort {
+    pub key: String,
+    pub descending: bool,
+    columns: &'static [&'static str],
+    id_column: &'static str,
+}
+
+impl Sort {
+    /// Parses `sort`, accepting only the keys in `allowed`, each listed with
+    /// the columns it orders by. Defaults to `id`, the column `id_column`.
+    pub fn new(
+        sort: Option<String>,
+        id_column: &'static str,
+        allowed: &[(&str, &'static [&'static str])],
+    ) -> Result<Self, CustomError> {
+        let sort = sort.unwrap_or_else(|| "id".into());
+
+        let (key, descending) = match sort.strip_prefix('-') {
+            Some(key) => (key.to_string(), true),
+            None => (sort, false),
+        };
+
+        let columns = match allowed.iter().find(|(allowed, _)| *allowed == key) {
+            Some((_, columns)) => *columns,
+            None if key == "id" => &[],
+            None => {
+                return Err(Custom(
+                    Status::BadRequest,
+                    Json(ErrorInfo::new(format!("Cannot sort by {}", key))),
+                ))
+            }
+        };
+
+        Ok(Sort {
+            key,
+            descending,
+            columns,
+            id_column,
+        })
+    }
+}
+
+impl std::fmt::Display for Sort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.descending {
+            write!(f, "-{}", self.key)
+        } else {
+            write!(f, "{}", self.key)
+        }
+    }
+}
+
+/// `WHERE` condition matching the rows that sort after a cursor.
+#[derive(Clone)]
+pub struct Keyset {
+    columns: &'static [&'static str],
+    id_column: &'static str,
+    descending: bool,
+    key: Vec<Option<SortValue>>,
+    id: i32,
+}
+
+impl Expression for Keyset {
+    type SqlType = Bool;
+}
+
+impl<QS> AppearsOnTable<QS> for Keyset {}
+
+impl<GB> ValidGrouping<GB> for Keyset {
+    type IsAggregate = is_aggregate::Never;
+}
+
+impl QueryId for Keyset {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl QueryFragment<Pg> for Keyset {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+        let (later, nulls) = if self.descending {
+            (" < ", " IS NOT NULL")
+        } else {
+            (" > ", " IS NULL")
+        };
+        let mut closing = String::new();
+
+        // Each column either already sorts the row later, or ties and leaves
+        // the decision to the next column, and finally to the id.
+        for (column, value) in self.columns.iter().zip(&self.key) {
+            out.push_sql("(");
+            out.push_sql(column);
+
+            match value {
+                Some(value) => {
+                    out.push_sql(later);
+                    push_value(&mut out, value)?;
+                    if !self.descending {
+                        out.push_sql(" OR ");
+                        out.push_sql(column);
+                        out.push_sql(nulls);
+                    }
+                    out.push_sql(" OR (");
+                    out.push_sql(column);
+                    out.push_sql(" = ");
+                    push_value(&mut out, value)?;
+                    out.push_sql(" AND ");
+                    closing.push_str("))");
+                }
+                None if self.descending => {
+                    out.push_sql(nulls);
+                    out.push_sql(" OR (");
+                    out.push_sql(column);
+                    out.push_sql(" IS NULL AND ");
+                    closing.push_str("))");
+                }
+                None => {
+                    out.push_sql(" IS NULL AND ");
+                    closing.push(')');
+                }
+            }
+        }
+
+        out.push_sql(self.id_column);
+        out.push_sql(later);
+        out.push_bind_param::<Integer, _>(&self.id)?;
+        out.push_sql(&closing);
+
+        Ok(())
+    }
+}
+
+fn push_value<'b>(out: &mut AstPass<'_, 'b, Pg>, value: &'b SortValue) -> QueryResult<()> {
+    match value {
+        SortValue::Int(value) => out.push_bind_param::<Integer, _>(value),
+        SortValue::Text(value) => out.push_bind_param::<Text, _>(value),
+        SortValue::Time(value) => out.push_bind_param::<Timestamp, _>(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::goods;
+    use crate::test_support::{
+        insert_category, insert_character, insert_circle, insert_goods, test_connection,
+        TestConnection,
+    };
+    use diesel::prelude::*;
+
+    /// Reads goods `ids` two at a time in `sort` order, following cursors.
+    fn read_pages(conn: &mut TestConnection, ids: &[i32], sort: &str) -> Vec<i32> {
+        let sort = Sort::new(
+            Some(sort.into()),
+            "goods.id",
+            &[("price", &["goods.price"])],
+        )
+        .unwrap();
+        let mut cursor = None;
+        let mut read = vec![];
+
+        loop {
+            let page_request = PageRequest::new(Some(2), cursor.as_deref(), &sort).unwrap();
+            let mut query = goods::table.filter(goods::id.eq_any(ids)).into_boxed();
+
+            query = match (sort.key.as_str(), sort.descending) {
+                ("price", false) => query.order_by((goods::price.asc(), goods::id.asc())),
+                ("price", true) => query.order_by((goods::price.desc(), goods::id.desc())),
+                (_, false) => query.order_by(goods::id.asc()),
+                (_, true) => query.order_by(goods::id.desc()),
+            };
+
+            if let Some(after) = page_request.after() {
+                query = query.filter(after);
+            }
+
+            let rows = query
+                .select((goods::id, goods::price))
+                .limit(page_request.fetch_limit())
+                .load::<(i32, Option<i32>)>(conn)
+                .unwrap();
+            let page = page_request.page(rows, 0, |(id, price)| {
+                let key = match sort.key.as_str() {
+                    "price" => vec![price.map(SortValue::from)],
+                    _ => vec![],
+                };
+                (key, *id)
+            });
+
+            read.extend(page.items.iter().map(|(id, _)| *id));
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return read,
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn cursors_walk_ties_and_nulls_in_order() {
+        let mut conn = test_connection();
+        let circle_id = insert_circle(&mut conn);
+        let category_id = insert_category(&mut conn);
+        let character_id = insert_character(&mut conn);
+        let ids = insert_goods(&mut conn, 6, circle_id, category_id, character_id);
+
+        for (id, price) in ids
+            .iter()
+            .zip([None, Some(200), Some(100), None, Some(100), Some(300)])
+        {
+            diesel::update(goods::table.find(id))
+                .set(goods::price.eq(price))
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        let by_price = vec![ids[2], ids[4], ids[1], ids[5], ids[0], ids[3]];
+        let mut by_price_desc = by_price.clone();
+        by_price_desc.reverse();
+        let mut by_id_desc = ids.clone();
+        by_id_desc.reverse();
+
+        assert_eq!(read_pages(&mut conn, &ids, "price"), by_price);
+        assert_eq!(read_pages(&mut conn, &ids, "-price"), by_price_desc);
+        assert_eq!(read_pages(&mut conn, &ids, "id"), ids);
+        assert_eq!(read_pages(&mut conn, &ids, "-id"), by_id_desc);
+    }
+
+    #[test]
+    fn cursors_only_resume_the_sort_they_came_from() {
+        let by_price = Sort::new(
+            Some("price".into()),
+            "goods.id",
+            &[("price", &["goods.price"])],
+        )
+        .unwrap();
+        let by_id = Sort::new(None, "goods.id", &[("price", &["goods.price"])]).unwrap();
+
+        let page = PageRequest::new(Some(1), None, &by_price).unwrap().page(
+            vec![(1, 100), (2, 200)],
+            2,
+            |(id, price)| (vec![Some(SortValue::from(*price))], *id),
+        );
+        let cursor = page.next_cursor.unwrap();
+
+        assert!(PageRequest::new(Some(1), Some(&cursor), &by_price).is_ok());
+        assert!(PageRequest::new(Some(1), Some(&cursor), &by_id).is_err());
+        assert!(PageRequest::new(Some(1), Some("not a cursor"), &by_price).is_err());
+    }
+}
@@ -0,0 +1,61 @@
+use diesel::expression::{is_aggregate, ValidGrouping};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_builder::QueryFragment;
+use diesel::sql_types::{Bool, Nullable, Text};
+
+use crate::utils::strings::escape_like;
+
+define_sql_function! {
+    /// Folds width, case, kana and long vowels of Japanese text.
+    fn search_key(input: Nullable<Text>) -> Nullable<Text>;
+}
+
+define_sql_function! {
+    /// Decomposes Hangul syllables into compatibility jamo.
+    fn hangul_jamo(input: Nullable<Text>) -> Nullable<Text>;
+}
+
+define_sql_function! {
+    /// Replaces Hangul syllables with their initial consonants.
+    fn hangul_chosung(input: Nullable<Text>) -> Nullable<Text>;
+}
+
+/// A `LIKE` pattern for `text` as a literal substring. Full-width wildcards
+/// are folded first, since NFKC in `search_key` would turn them into live
+/// ones.
+pub fn contains_pattern(text: &str) -> String {
+    let folded = text
+        .replace('％', "%")
+        .replace('＿', "_")
+        .replace('＼', "\\");
+
+    format!("%{}%", escape_like(&folded))
+}
+
+/// Matches `column` against a name typed by the user as a literal substring:
+/// by search key so that `ｱﾘｽ` finds `ありす`, by jamo so that a half-typed
+/// syllable still matches (`블루앜` finds `블루아카`), and by initial consonants
+/// (`ㅂㄹㅇㅋ`).
+pub fn name_matches<QS, C>(
+    column: C,
+    name: &str,
+) -> Box<dyn BoxableExpression<QS, Pg, SqlType = Nullable<Bool>>>
+where
+    C: Expression<SqlType = Nullable<Text>>
+        + SelectableExpression<QS>
+        + QueryFragment<Pg>
+        + ValidGrouping<(), IsAggregate = is_aggregate::No>
+        + Clone
+        + Send
+        + 'static,
+{
+    let pattern = contains_pattern(name);
+
+    Box::new(
+        search_key(column.clone())
+            .like(search_key(pattern.clone()))
+            .or(hangul_jamo(column.clone()).like(hangul_jamo(pattern.clone())))
+            .or(hangul_chosung(column).like(pattern)),
+    )
+}